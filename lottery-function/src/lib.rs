@@ -8,6 +8,8 @@ declare_id!("6AKXZiKbmj3D45bDZpa9fo6vUV4qGeeeRCZ5qRhE4Ve4");
 
 pub const PROGRAM_SEED: &[u8] = b"SIMPLE_LOTTERY";
 pub const LOTTERY_SEED: &[u8] = b"LOTTERY_STATE";
+pub const TREASURY_SEED: &[u8] = b"TREASURY";
+pub const LOTTERY_VAULT_SEED: &[u8] = b"LOTTERY_VAULT";
 
 /// The maximum number of tickets allowed to enter a lottery.
 /// This could be dynamic but for this example its hard coded.
@@ -32,9 +34,7 @@ pub async fn load_account<T: bytemuck::Pod + Discriminator>(
     }
 
     if account.data.len() < T::discriminator().len() {
-        return Err(SbError::CustomMessage(
-            "no discriminator found".to_string(),
-        ));
+        return Err(SbError::CustomMessage("no discriminator found".to_string()));
     }
 
     let mut disc_bytes = [0u8; 8];
@@ -59,6 +59,22 @@ pub struct ProgramState {
     pub authority: Pubkey,
     /// Switchboard Function pubkey.
     pub switchboard_function: Pubkey,
+    /// Account proposed to take over as authority, or the default pubkey if none.
+    pub pending_authority: Pubkey,
+    /// Pauses every pausable instruction when set.
+    pub paused: bool,
+    /// Bitmask of individually paused instructions.
+    pub paused_ixs: u8,
+    /// The number of slots a lottery runs for when created without a duration.
+    pub lottery_duration_slots: u32,
+    /// The bounty, in lamports, paid to oracles to draw each lottery.
+    pub default_bounty: u64,
+    /// The number of slots each lottery's Switchboard request can be processed in once it's valid.
+    pub slots_until_expiration: u64,
+    /// The protocol fee, in basis points, taken from each jackpot and sent to the treasury.
+    pub fee_bps: u16,
+    /// The number of slots after a lottery closes before its tickets can be refunded if undrawn.
+    pub refund_delay_slots: u64,
 }
 impl ProgramState {
     pub async fn fetch(
@@ -100,8 +116,45 @@ pub struct LotteryState {
 
     // Data
     pub tickets: [Pubkey; MAX_TICKETS],
+
+    /// The lottery's id within its LotteryHost, used in the PDA seeds.
+    pub lottery_id: u64,
+
+    // Recurring config
+    /// Whether draw_winner schedules another round after paying out.
+    pub recurring: bool,
+    /// The number of slots each round lasts.
+    pub duration_slots: u32,
+    /// The current round, starting at 0.
+    pub round: u32,
+    /// The winner of the previous round.
+    pub previous_winner: Pubkey,
+
+    /// Whether the lottery was never drawn and its tickets are being refunded.
+    pub refunding: bool,
 }
 impl LotteryState {
+    /// The tickets sold so far. Only the first `num_tickets` entries of `tickets` are set.
+    pub fn sold_tickets(&self) -> &[Pubkey] {
+        let num_tickets = (self.num_tickets as usize).min(MAX_TICKETS);
+        &self.tickets[..num_tickets]
+    }
+
+    /// Selects the winning ticket as `tickets[randomness % num_tickets]`, or the lottery
+    /// authority if no tickets were sold. Must match the program's `LotteryState::select_winner`,
+    /// which is where it's tested.
+    pub fn select_winner(&self, randomness: &[u8; 32]) -> Pubkey {
+        let tickets = self.sold_tickets();
+        if tickets.is_empty() {
+            return self.authority;
+        }
+
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&randomness[..8]);
+        let index = u64::from_le_bytes(bytes) % tickets.len() as u64;
+        tickets[index as usize]
+    }
+
     pub async fn fetch(
         client: &RpcClient,
         pubkey: &Pubkey,
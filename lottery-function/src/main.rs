@@ -27,36 +27,40 @@ async fn main() {
     let lottery = LotteryState::fetch(&runner.client, &params.lottery_key, &params.program_id)
         .await
         .unwrap();
+    let lottery_id = lottery.lottery_id;
+    assert_eq!(lottery_id, params.lottery_id, "lottery id mismatch");
 
-    // Determine the winner
-    let winning_index = generate_randomness(0, lottery.tickets.len() as u32 - 1);
-    let winner: Pubkey = if lottery.tickets.is_empty() {
-        lottery.authority
-    } else {
-        lottery.tickets.as_slice()[winning_index as usize]
-    };
+    // Generate the randomness and derive the winner the same way the program will on-chain
+    let randomness = generate_randomness();
+    let winner: Pubkey = lottery.select_winner(&randomness);
 
     // IXN DATA:
     // LEN: 40 bytes
     // [0-8]: Anchor Ixn Discriminator
-    // [9-40]: Winning Pubkey (32 bytes)
+    // [9-40]: Randomness (32 bytes)
     let mut ixn_data = get_ixn_discriminator("draw_winner").to_vec();
-    ixn_data.append(&mut winner.to_bytes().to_vec());
+    ixn_data.append(&mut randomness.to_vec());
 
     let request_pubkey = runner.function_request_key.unwrap();
 
     // ACCOUNTS:
     // 1. Lottery (mut): our user who guessed
     // 2. Escrow (mut):
-    // 3. Winner (mut):
+    // 3. Winner (mut): must match the ticket selected by the randomness
     // 4. Switchboard Program
     // 5. Switchboard State
-    // 6. Switchboard Function
+    // 6. Switchboard Function (mut):
     // 7. Switchboard Function Request (mut):
     // 8. Enclave Signer (signer): our Gramine generated keypair
     // 9. Switchboard Request Escrow (mut):
     // 10. System Program
     // 11. Token Program
+    // 12. Program State
+    // 13. Treasury (mut): collects the protocol fee
+    // 14. Switchboard Attestation Queue
+    // 15. Switchboard Mint
+    // 16. Lottery Vault (mut): funds the escrow when a recurring lottery is rescheduled
+    // 17. Associated Token Program
     let draw_winner_ixn = Instruction {
         program_id: params.program_id,
         data: ixn_data,
@@ -66,7 +70,7 @@ async fn main() {
             AccountMeta::new(winner, false),
             AccountMeta::new_readonly(SWITCHBOARD_ATTESTATION_PROGRAM_ID, false),
             AccountMeta::new_readonly(AttestationProgramState::get_pda(), false),
-            AccountMeta::new_readonly(runner.function, false),
+            AccountMeta::new(runner.function, false),
             AccountMeta::new(request_pubkey, false),
             AccountMeta::new_readonly(runner.signer, true),
             AccountMeta::new(
@@ -78,6 +82,32 @@ async fn main() {
             ),
             AccountMeta::new_readonly(solana_program::system_program::ID, false),
             AccountMeta::new_readonly(anchor_spl::token::ID, false),
+            AccountMeta::new_readonly(
+                Pubkey::find_program_address(&[PROGRAM_SEED], &params.program_id).0,
+                false,
+            ),
+            AccountMeta::new(
+                Pubkey::find_program_address(&[PROGRAM_SEED, TREASURY_SEED], &params.program_id).0,
+                false,
+            ),
+            AccountMeta::new_readonly(
+                runner
+                    .function_request_data
+                    .as_ref()
+                    .unwrap()
+                    .attestation_queue,
+                false,
+            ),
+            AccountMeta::new_readonly(anchor_spl::token::spl_token::native_mint::ID, false),
+            AccountMeta::new(
+                Pubkey::find_program_address(
+                    &[LOTTERY_VAULT_SEED, params.lottery_key.as_ref()],
+                    &params.program_id,
+                )
+                .0,
+                false,
+            ),
+            AccountMeta::new_readonly(anchor_spl::associated_token::ID, false),
         ],
     };
 
@@ -90,72 +120,19 @@ async fn main() {
     runner.emit(ixs).await.unwrap();
 }
 
-fn generate_randomness(min: u32, max: u32) -> u32 {
-    if min == max {
-        return min;
-    }
-    if min > max {
-        return generate_randomness(max, min);
-    }
-
-    // We add one so its inclusive [min, max]
-    let window = (max + 1) - min;
-
-    let mut bytes: [u8; 4] = [0u8; 4];
+fn generate_randomness() -> [u8; 32] {
+    let mut bytes: [u8; 32] = [0u8; 32];
     Gramine::read_rand(&mut bytes).expect("gramine failed to generate randomness");
-    let raw_result: &[u32] = bytemuck::cast_slice(&bytes[..]);
-
-    (raw_result[0] % window) + min
+    bytes
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // 1. Check when lower_bound is greater than upper_bound
-    #[test]
-    fn test_generate_randomness_with_flipped_bounds() {
-        let min = 100;
-        let max = 50;
-
-        let result = generate_randomness(100, 50);
-        assert!(result >= max && result < min);
-    }
-
-    // 2. Check when lower_bound is equal to upper_bound
-    #[test]
-    fn test_generate_randomness_with_equal_bounds() {
-        let bound = 100;
-        assert_eq!(generate_randomness(bound, bound), bound);
-    }
-
-    // 3. Test within a range
+    // Two draws should never produce the same 32 bytes (probabilistically)
     #[test]
-    fn test_generate_randomness_within_bounds() {
-        let min = 100;
-        let max = 200;
-
-        let result = generate_randomness(min, max);
-
-        assert!(result >= min && result < max);
-    }
-
-    // 4. Test randomness distribution (not truly deterministic, but a sanity check)
-    #[test]
-    fn test_generate_randomness_distribution() {
-        let min = 0;
-        let max = 9;
-
-        let mut counts = vec![0; 10];
-        for _ in 0..1000 {
-            let result = generate_randomness(min, max);
-            let index: usize = result as usize;
-            counts[index] += 1;
-        }
-
-        // Ensure all counts are non-zero (probabilistically should be the case)
-        for count in counts.iter() {
-            assert!(*count > 0);
-        }
+    fn test_generate_randomness_is_unique() {
+        assert_ne!(generate_randomness(), generate_randomness());
     }
 }
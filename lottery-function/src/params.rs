@@ -3,6 +3,7 @@ use crate::*;
 pub struct ContainerParams {
     pub program_id: Pubkey,
     pub lottery_key: Pubkey,
+    pub lottery_id: u64,
 }
 
 impl ContainerParams {
@@ -11,7 +12,7 @@ impl ContainerParams {
 
         let mut program_id: Pubkey = Pubkey::default();
         let mut lottery_key: Pubkey = Pubkey::default();
-
+        let mut lottery_id: Option<u64> = None;
 
         for env_pair in params.split(',') {
             let pair: Vec<&str> = env_pair.splitn(2, '=').collect();
@@ -19,6 +20,7 @@ impl ContainerParams {
                 match pair[0] {
                     "PID" => program_id = Pubkey::from_str(pair[1]).unwrap(),
                     "LOTTERY" => lottery_key = Pubkey::from_str(pair[1]).unwrap(),
+                    "LOTTERY_ID" => lottery_id = Some(pair[1].parse().unwrap()),
                     _ => {}
                 }
             }
@@ -35,9 +37,14 @@ impl ContainerParams {
             ));
         }
 
+        let lottery_id = lottery_id.ok_or(SbError::CustomMessage(
+            "LOTTERY_ID cannot be undefined".to_string(),
+        ))?;
+
         Ok(Self {
             program_id,
-            lottery_key
+            lottery_key,
+            lottery_id,
         })
     }
 }
@@ -49,9 +56,10 @@ mod tests {
     #[test]
     fn test_params_decode() {
         let request_params_string = format!(
-            "PID={},LOTTERY={}",
+            "PID={},LOTTERY={},LOTTERY_ID={}",
+            anchor_spl::token::ID,
             anchor_spl::token::ID,
-            anchor_spl::token::ID
+            7
         );
         let request_params_bytes = request_params_string.into_bytes();
 
@@ -59,5 +67,6 @@ mod tests {
 
         assert_eq!(params.program_id, anchor_spl::token::ID);
         assert_eq!(params.lottery_key, anchor_spl::token::ID);
+        assert_eq!(params.lottery_id, 7);
     }
 }
@@ -2,27 +2,264 @@
 // Program: Solana Simple Randomness
 // This Solana program will allow you to request a new random value for a given user.
 // The following instructions are supported:
-// - guess:       Submits a new guess for the current user. This will make a CPI to the
-//                Switchboard Function Request account to trigger the off-chain docker container.
-// - settle:      This ixn will be invoked by the Switchboard oracle off-chain and will provide
-//                the random result to determine if the user won.
+// - initialize_house:  Initializes the house vault which escrows user wagers and pays out winners.
+//                      Only the program's upgrade authority can initialize it.
+// - set_max_exposure:  Sets the share of the house vault that unsettled guesses can be exposed to.
+// - set_result_bounds: Sets the lowest and highest result a guess' result range can request.
+// - withdraw_house:    Allows the house authority to withdraw SOL from the house vault, as long as
+//                      the remaining vault can cover all unsettled guesses.
+// - guess:             Submits a new guess and wager for the current user. The wager is escrowed
+//                      in the house vault and a CPI is made to the Switchboard Function Request
+//                      account to trigger the off-chain docker container. The users authority must
+//                      sign.
+// - guess_relayed:     Submits a guess signed off-chain by the users authority, verified with the
+//                      ed25519 program, so a relayer can pay for it. Each signed guess carries a
+//                      nonce tracked by a RelayState PDA that is never closed, so it can't be
+//                      replayed.
+// - settle:            This ixn will be invoked by the Switchboard oracle off-chain and will provide
+//                      the random result to determine if the user won. Winners are paid out from
+//                      the house vault.
+// - guess_with_ticket: Submits a new guess and wager on a nonce-seeded ticket. Each ticket has its
+//                      own Switchboard Function Request so an authority can have many guesses in
+//                      flight at once.
+// - settle_ticket:     The ticket equivalent of settle, invoked by the Switchboard oracle off-chain.
+// - cancel_ticket:     Closes a ticket and returns its rent to the authority. A ticket that was never
+//                      settled can only be cancelled once its Switchboard request has expired, and
+//                      its wager is refunded from the house vault.
+// - close:             Closes the users current Switchboard Function Request account, its escrow,
+//                      and the users randomness account. All SOL is returned to the users authority,
+//                      along with the wager of a guess that timed out without being settled. A
+//                      current request that was already closed is skipped.
+// - close_request:     Closes a previous Switchboard Function Request account created by guess and
+//                      returns its rent to the users authority.
 
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
 use switchboard_solana::prelude::*;
 
 declare_id!("E5MAszjz8qZZDHKqQ21g5wYuhMTjMbk1L4L4jBFXMgqG");
 
 pub const PROGRAM_SEED: &[u8] = b"SIMPLE_RANDOMNESS";
 pub const USER_SEED: &[u8] = b"RANDOMNESS_USER";
+pub const HOUSE_SEED: &[u8] = b"HOUSE_VAULT";
+pub const RELAY_SEED: &[u8] = b"RELAY_NONCE";
 
 // [MIN_RESULT, MAX_RESULT]
-/// The minimum guess that can be submitted, inclusive.
+/// The default lower bound for any guess' result range, inclusive.
 pub const MIN_RESULT: u32 = 1;
-/// The maximum guess that can be submitted, inclusive.
+/// The default upper bound for any guess' result range, inclusive.
 pub const MAX_RESULT: u32 = 10;
 
+/// The highest configurable result range upper bound, so the Switchboard Function's inclusive
+/// `max_result - min_result + 1` window always fits in a u32.
+pub const MAX_RESULT_BOUND: u32 = u32::MAX - 1;
+
 /// The minimum amount of time before a user can re-guess if the previous guess hasnt settled.
 pub const REQUEST_TIMEOUT: i64 = 60;
 
+/// The number of slots after a guess before anyone can close its Switchboard request, ~ 1 hour at
+/// 400 ms/slot.
+pub const GARBAGE_COLLECTION_SLOTS: u64 = 9_000;
+
+/// The maximum house edge, in basis points, that can be configured.
+pub const MAX_HOUSE_EDGE_BPS: u16 = 10_000;
+
+/// The maximum share of the house vault, in basis points, that unsettled guesses can be exposed to.
+pub const MAX_EXPOSURE_BPS: u16 = 10_000;
+
+/// Prefixes every relayed guess message so the authority's signature can't be reused elsewhere.
+pub const RELAYED_GUESS_DOMAIN: &[u8] = b"SIMPLE_RANDOMNESS_RELAYED_GUESS";
+
+/// Returns the message an authority signs to let a relayer submit the given guess on its behalf.
+pub fn relayed_guess_message(
+    authority: &Pubkey,
+    nonce: u32,
+    guess: u32,
+    wager: u64,
+    min_result: u32,
+    max_result: u32,
+) -> Vec<u8> {
+    let mut message = RELAYED_GUESS_DOMAIN.to_vec();
+    message.extend_from_slice(crate::id().as_ref());
+    message.extend_from_slice(authority.as_ref());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message.extend_from_slice(&guess.to_le_bytes());
+    message.extend_from_slice(&wager.to_le_bytes());
+    message.extend_from_slice(&min_result.to_le_bytes());
+    message.extend_from_slice(&max_result.to_le_bytes());
+    message
+}
+
+/// Verifies the instruction before the current one checks the signer's ed25519 signature over the
+/// message.
+pub fn verify_ed25519_ix(
+    instructions: &AccountInfo,
+    signer: &Pubkey,
+    message: &[u8],
+) -> anchor_lang::Result<()> {
+    let current_index = load_current_index_checked(instructions)?;
+    if current_index == 0 {
+        return Err(error!(SimpleRandomnessError::InvalidRelaySignature));
+    }
+
+    let ix = load_instruction_at_checked(usize::from(current_index - 1), instructions)?;
+    if ix.program_id != ed25519_program::ID || !ix.accounts.is_empty() {
+        return Err(error!(SimpleRandomnessError::InvalidRelaySignature));
+    }
+
+    verify_ed25519_data(&ix.data, signer, message)
+}
+
+/// Verifies ed25519 program instruction data holds a single signature whose public key and message
+/// match the given ones and are stored in the instruction itself.
+pub fn verify_ed25519_data(
+    data: &[u8],
+    signer: &Pubkey,
+    message: &[u8],
+) -> anchor_lang::Result<()> {
+    // [0]: number of signatures, [1]: padding, [2..16]: signature offsets
+    if data.len() < 16 || data[0] != 1 {
+        return Err(error!(SimpleRandomnessError::InvalidRelaySignature));
+    }
+    let read_u16 =
+        |offset: usize| usize::from(u16::from_le_bytes([data[offset], data[offset + 1]]));
+    let (signature_ix, public_key_offset, public_key_ix) = (read_u16(4), read_u16(6), read_u16(8));
+    let (message_offset, message_size, message_ix) = (read_u16(10), read_u16(12), read_u16(14));
+
+    // The signature, public key, and message must all live in the ed25519 instruction
+    let this_ix = usize::from(u16::MAX);
+    if signature_ix != this_ix || public_key_ix != this_ix || message_ix != this_ix {
+        return Err(error!(SimpleRandomnessError::InvalidRelaySignature));
+    }
+
+    let public_key = data.get(public_key_offset..public_key_offset + 32);
+    let signed_message = data.get(message_offset..message_offset + message_size);
+    if public_key != Some(signer.as_ref()) || signed_message != Some(message) {
+        return Err(error!(SimpleRandomnessError::InvalidRelaySignature));
+    }
+
+    Ok(())
+}
+
+/// Represents the house vault.
+/// Holds all escrowed wagers and the liquidity used to pay out winners.
+#[account]
+pub struct HouseState {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// Account authorized to withdraw from the house vault.
+    pub authority: Pubkey,
+    /// The cut of each payout retained by the house, in basis points.
+    pub house_edge_bps: u16,
+    /// The sum of the payouts owed if every unsettled guess wins, or its wager if that is larger.
+    pub outstanding_liability: u64,
+    /// The share of the house vault, in basis points, that `outstanding_liability` may reach.
+    pub max_exposure_bps: u16,
+    /// The lowest result range lower bound a guess can request, inclusive.
+    pub min_result: u32,
+    /// The highest result range upper bound a guess can request, inclusive.
+    pub max_result: u32,
+}
+
+impl HouseState {
+    /// Returns the amount of lamports the house can spend without dropping below rent exemption.
+    pub fn available_lamports(account: &AccountInfo) -> anchor_lang::Result<u64> {
+        let rent_exempt_lamports = Rent::get()?.minimum_balance(account.data_len());
+        Ok(account.lamports().saturating_sub(rent_exempt_lamports))
+    }
+
+    /// Verifies a guess' result range is well formed and within the program-wide bounds.
+    pub fn validate_range(&self, min_result: u32, max_result: u32) -> anchor_lang::Result<()> {
+        if min_result > max_result || min_result < self.min_result || max_result > self.max_result {
+            return Err(error!(SimpleRandomnessError::InvalidResultRange));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the amount paid out to a winning guess for the given wager and result range, net of
+    /// the house edge.
+    pub fn payout(&self, wager: u64, min_result: u32, max_result: u32) -> anchor_lang::Result<u64> {
+        let gross = (wager as u128)
+            .checked_mul(u128::from(max_result.saturating_sub(min_result)) + 1)
+            .ok_or(error!(SimpleRandomnessError::MathOverflow))?;
+        let edge = gross * u128::from(self.house_edge_bps) / u128::from(MAX_HOUSE_EDGE_BPS);
+        u64::try_from(gross - edge).map_err(|_| error!(SimpleRandomnessError::MathOverflow))
+    }
+
+    /// Returns the maximum open liability allowed for the given vault balance.
+    pub fn max_exposure(&self, house_lamports: u64) -> u64 {
+        (u128::from(house_lamports) * u128::from(self.max_exposure_bps)
+            / u128::from(MAX_EXPOSURE_BPS)) as u64
+    }
+
+    /// Returns the most the house can owe on a wager, either its max payout or, if the guess times
+    /// out, a refund of the wager itself.
+    pub fn liability(
+        &self,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<u64> {
+        Ok(self.payout(wager, min_result, max_result)?.max(wager))
+    }
+
+    /// Adds the liability of a new wager to the open liability, as long as it stays within the
+    /// exposure limit for the given vault balance.
+    pub fn reserve(
+        &mut self,
+        house_lamports: u64,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<()> {
+        let outstanding_liability = self
+            .outstanding_liability
+            .checked_add(self.liability(wager, min_result, max_result)?)
+            .ok_or(error!(SimpleRandomnessError::MathOverflow))?;
+        if outstanding_liability > self.max_exposure(house_lamports) {
+            return Err(error!(SimpleRandomnessError::ExposureLimitExceeded));
+        }
+        self.outstanding_liability = outstanding_liability;
+
+        Ok(())
+    }
+
+    /// Removes the liability of a wager from the open liability and returns its max payout.
+    pub fn release(
+        &mut self,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<u64> {
+        let liability = self.liability(wager, min_result, max_result)?;
+        self.outstanding_liability = self.outstanding_liability.saturating_sub(liability);
+
+        self.payout(wager, min_result, max_result)
+    }
+
+    /// Transfers lamports out of the house vault.
+    pub fn pay_out(
+        house: &AccountInfo,
+        destination: &AccountInfo,
+        amount: u64,
+    ) -> anchor_lang::Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        if HouseState::available_lamports(house)? < amount {
+            return Err(error!(SimpleRandomnessError::InsufficientHouseFunds));
+        }
+
+        **house.try_borrow_mut_lamports()? -= amount;
+        **destination.try_borrow_mut_lamports()? += amount;
+
+        Ok(())
+    }
+}
+
 /// Represents a users config.
 /// PDA scheme enforces 1 user per authority.
 #[account]
@@ -35,6 +272,12 @@ pub struct UserState {
     pub switchboard_request: Pubkey,
     /// The current users guess.
     pub guess: u32,
+    /// The amount of lamports wagered on the current guess.
+    pub wager: u64,
+    /// The lower bound of the current guess' result range, inclusive.
+    pub min_result: u32,
+    /// The upper bound of the current guess' result range, inclusive.
+    pub max_result: u32,
     /// The Switchboard Function result.
     pub result: u32,
     /// The timestamp when the current guess was placed.
@@ -43,48 +286,219 @@ pub struct UserState {
     pub settled_timestamp: i64,
 }
 
+/// Tracks the relayed guess nonce for an authority.
+/// Never closed, so a signed guess can't be replayed after the users account is closed and
+/// re-created.
+#[account]
+pub struct RelayState {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// The nonce the next relayed guess must be signed with.
+    pub nonce: u32,
+}
+
+/// Represents a single in-flight guess.
+/// PDA scheme allows many tickets per authority, one per nonce.
+#[account]
+pub struct GuessTicket {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// Account which placed the guess and receives any winnings.
+    pub authority: Pubkey,
+    /// The nonce used to derive this ticket.
+    pub nonce: u64,
+    /// Switchboard Function Request pubkey.
+    pub switchboard_request: Pubkey,
+    /// The guess for this ticket.
+    pub guess: u32,
+    /// The amount of lamports wagered on this ticket.
+    pub wager: u64,
+    /// The lower bound of this ticket's result range, inclusive.
+    pub min_result: u32,
+    /// The upper bound of this ticket's result range, inclusive.
+    pub max_result: u32,
+    /// The Switchboard Function result.
+    pub result: u32,
+    /// The timestamp when the guess was placed.
+    pub request_timestamp: i64,
+    /// The timestamp when the request was settled.
+    pub settled_timestamp: i64,
+}
+
 #[program]
 pub mod super_simple_randomness {
     use super::*;
 
-    pub fn guess(ctx: Context<Guess>, guess: u32) -> anchor_lang::Result<()> {
-        if ctx.accounts.user.request_timestamp > 0
-            && ctx.accounts.user.settled_timestamp == 0
-            && Clock::get()?.unix_timestamp - ctx.accounts.user.request_timestamp < REQUEST_TIMEOUT
+    pub fn initialize_house(
+        ctx: Context<InitializeHouse>,
+        house_edge_bps: u16,
+        max_exposure_bps: u16,
+    ) -> anchor_lang::Result<()> {
+        if house_edge_bps > MAX_HOUSE_EDGE_BPS {
+            return Err(error!(SimpleRandomnessError::InvalidHouseEdge));
+        }
+        if max_exposure_bps > MAX_EXPOSURE_BPS {
+            return Err(error!(SimpleRandomnessError::InvalidExposureLimit));
+        }
+
+        ctx.accounts.house.bump = ctx.bumps.house;
+        ctx.accounts.house.authority = ctx.accounts.authority.key();
+        ctx.accounts.house.house_edge_bps = house_edge_bps;
+        ctx.accounts.house.max_exposure_bps = max_exposure_bps;
+        ctx.accounts.house.min_result = MIN_RESULT;
+        ctx.accounts.house.max_result = MAX_RESULT;
+
+        Ok(())
+    }
+
+    pub fn set_max_exposure(
+        ctx: Context<SetMaxExposure>,
+        max_exposure_bps: u16,
+    ) -> anchor_lang::Result<()> {
+        if max_exposure_bps > MAX_EXPOSURE_BPS {
+            return Err(error!(SimpleRandomnessError::InvalidExposureLimit));
+        }
+
+        ctx.accounts.house.max_exposure_bps = max_exposure_bps;
+
+        Ok(())
+    }
+
+    pub fn set_result_bounds(
+        ctx: Context<SetResultBounds>,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<()> {
+        if min_result > max_result || max_result == 0 || max_result > MAX_RESULT_BOUND {
+            return Err(error!(SimpleRandomnessError::InvalidResultRange));
+        }
+
+        ctx.accounts.house.min_result = min_result;
+        ctx.accounts.house.max_result = max_result;
+
+        Ok(())
+    }
+
+    pub fn withdraw_house(ctx: Context<WithdrawHouse>, amount: u64) -> anchor_lang::Result<()> {
+        // The remaining vault must still cover every unsettled guess
+        let house = ctx.accounts.house.to_account_info();
+        let house_lamports = HouseState::available_lamports(&house)?;
+        if house_lamports < amount
+            || house_lamports - amount < ctx.accounts.house.outstanding_liability
         {
-            return Err(error!(SimpleRandomnessError::RequestNotReady));
+            return Err(error!(SimpleRandomnessError::InsufficientHouseFunds));
         }
 
-        // Initialize user account if needed
-        if ctx.accounts.user.bump == 0 {
-            // ctx.accounts.user.bump = *ctx.bumps.get("user").unwrap();
-            ctx.accounts.user.bump = ctx.bumps.user;
-            ctx.accounts.user.authority = ctx.accounts.authority.key();
+        **house.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.destination.try_borrow_mut_lamports()? += amount;
+
+        Ok(())
+    }
+
+    pub fn guess(
+        ctx: Context<Guess>,
+        guess: u32,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<()> {
+        // Relayers must use guess_relayed to guess on an authority's behalf
+        if !ctx.accounts.authority.is_signer {
+            return Err(error!(SimpleRandomnessError::InvalidAuthority));
         }
 
-        // Set new guess data
-        ctx.accounts.user.switchboard_request = ctx.accounts.switchboard_request.key();
-        ctx.accounts.user.guess = guess;
-        ctx.accounts.user.result = 0;
-        ctx.accounts.user.request_timestamp = Clock::get()?.unix_timestamp;
-        ctx.accounts.user.settled_timestamp = 0;
+        ctx.accounts
+            .place(ctx.bumps.user, guess, wager, min_result, max_result)
+    }
 
-        // Trigger the Switchboard request
-        // This will instruct the off-chain oracles to execute your docker container and relay
-        // the result back to our program via the 'settle' instruction.
+    pub fn guess_relayed(
+        ctx: Context<GuessRelayed>,
+        nonce: u32,
+        guess: u32,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<()> {
+        // The authority must have signed this exact guess off-chain
+        let authority = ctx.accounts.guess.authority.key();
+        verify_ed25519_ix(
+            &ctx.accounts.instructions,
+            &authority,
+            &relayed_guess_message(&authority, nonce, guess, wager, min_result, max_result),
+        )?;
+
+        // Each signed guess can only be relayed once
+        let relay = &mut ctx.accounts.relay;
+        if nonce != relay.nonce {
+            return Err(error!(SimpleRandomnessError::InvalidRelayNonce));
+        }
+        relay.bump = ctx.bumps.relay;
+        relay.nonce = nonce
+            .checked_add(1)
+            .ok_or(error!(SimpleRandomnessError::MathOverflow))?;
+
+        ctx.accounts
+            .guess
+            .place(ctx.bumps.guess.user, guess, wager, min_result, max_result)
+    }
+
+    pub fn guess_with_ticket(
+        ctx: Context<GuessWithTicket>,
+        nonce: u64,
+        guess: u32,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<()> {
+        ctx.accounts.house.validate_range(min_result, max_result)?;
+        if !(min_result..=max_result).contains(&guess) {
+            return Err(error!(SimpleRandomnessError::InvalidGuess));
+        }
+
+        // Make sure the open liability across all unsettled guesses stays within the exposure limit
+        let house = ctx.accounts.house.to_account_info();
+        let house_lamports = HouseState::available_lamports(&house)?;
+        ctx.accounts
+            .house
+            .reserve(house_lamports, wager, min_result, max_result)?;
 
+        // Escrow the wager in the house vault
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: house,
+                },
+            ),
+            wager,
+        )?;
+
+        let ticket = &mut ctx.accounts.ticket;
+        ticket.bump = ctx.bumps.ticket;
+        ticket.authority = ctx.accounts.authority.key();
+        ticket.nonce = nonce;
+        ticket.switchboard_request = ctx.accounts.switchboard_request.key();
+        ticket.guess = guess;
+        ticket.wager = wager;
+        ticket.min_result = min_result;
+        ticket.max_result = max_result;
+        ticket.request_timestamp = Clock::get()?.unix_timestamp;
+
+        // Trigger a dedicated Switchboard request for this ticket so it can be settled
+        // independently of any other in-flight tickets.
         let request_params = format!(
-            "PID={},MIN_RESULT={},MAX_RESULT={},USER={}",
+            "PID={},MIN_RESULT={},MAX_RESULT={},TICKET={},AUTHORITY={}",
             crate::id(),
-            MIN_RESULT,
-            MAX_RESULT,
-            ctx.accounts.user.key(),
+            min_result,
+            max_result,
+            ctx.accounts.ticket.key(),
+            ctx.accounts.authority.key(),
         );
 
-        // https://docs.rs/switchboard-solana/latest/switchboard_solana/attestation_program/instructions/request_init_and_trigger/index.html
         let request_init_ctx = FunctionRequestInitAndTrigger {
             request: ctx.accounts.switchboard_request.clone(),
-            authority: ctx.accounts.user.to_account_info(),
+            authority: ctx.accounts.ticket.to_account_info(),
             function: ctx.accounts.switchboard_function.to_account_info(),
             function_authority: None,
             escrow: ctx.accounts.switchboard_request_escrow.clone(),
@@ -96,35 +510,23 @@ pub mod super_simple_randomness {
             token_program: ctx.accounts.token_program.to_account_info(),
             associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
         };
-        let user_authority_pubkey = ctx.accounts.authority.key();
+        let authority_pubkey = ctx.accounts.authority.key();
+        let nonce_bytes = nonce.to_le_bytes();
         let seeds = &[
             USER_SEED,
-            user_authority_pubkey.as_ref(),
-            &[ctx.accounts.user.bump],
+            authority_pubkey.as_ref(),
+            nonce_bytes.as_ref(),
+            &[ctx.accounts.ticket.bump],
         ];
 
         request_init_ctx.invoke_signed(
             ctx.accounts.switchboard.clone(),
-            // bounty - optional fee to reward oracles for priority processing
-            // default: 0 lamports
             None,
-            // slots_until_expiration - optional max number of slots the request can be processed in
-            // default: 2250 slots, ~ 15 min at 400 ms/slot
-            // minimum: 150 slots, ~ 1 min at 400 ms/slot
             None,
-            // max_container_params_len - the length of the vec containing the container params
-            // default: 256 bytes
             Some(512),
-            // container_params - the container params
-            // default: empty vec
             Some(request_params.into_bytes()),
-            // garbage_collection_slot - the slot when the request can be closed by anyone and is considered dead
-            // default: None, only authority can close the request
-            None,
-            // valid_after_slot - schedule a request to execute in N slots
-            // default: 0 slots, valid immediately for oracles to process
+            Some(Clock::get()?.slot + GARBAGE_COLLECTION_SLOTS),
             None,
-            // signer seeds
             &[seeds],
         )?;
 
@@ -132,7 +534,7 @@ pub mod super_simple_randomness {
     }
 
     pub fn settle(ctx: Context<Settle>, result: u32) -> anchor_lang::Result<()> {
-        if !(MIN_RESULT..MAX_RESULT).contains(&result) {
+        if !(ctx.accounts.user.min_result..=ctx.accounts.user.max_result).contains(&result) {
             return Err(error!(SimpleRandomnessError::RandomResultOutOfBounds));
         }
 
@@ -143,19 +545,183 @@ pub mod super_simple_randomness {
         ctx.accounts.user.result = result;
         ctx.accounts.user.settled_timestamp = Clock::get()?.unix_timestamp;
 
-        // TODO: handle any custom game logic here
+        let user_won = ctx.accounts.user.result == ctx.accounts.user.guess;
+
+        // Release the guess' liability and pay out the winner from the house vault
+        let max_payout = ctx.accounts.house.release(
+            ctx.accounts.user.wager,
+            ctx.accounts.user.min_result,
+            ctx.accounts.user.max_result,
+        )?;
+        let payout = if user_won { max_payout } else { 0 };
+        HouseState::pay_out(
+            &ctx.accounts.house.to_account_info(),
+            &ctx.accounts.authority,
+            payout,
+        )?;
 
         emit!(UserGuessSettled {
             user: ctx.accounts.user.key(),
             user_guess: ctx.accounts.user.guess,
             result: ctx.accounts.user.result,
-            user_won: ctx.accounts.user.result == ctx.accounts.user.guess,
+            user_won,
+            wager: ctx.accounts.user.wager,
+            payout,
             request_timestamp: ctx.accounts.user.request_timestamp,
             settled_timestamp: ctx.accounts.user.settled_timestamp
         });
 
         Ok(())
     }
+
+    pub fn settle_ticket(ctx: Context<SettleTicket>, result: u32) -> anchor_lang::Result<()> {
+        if !(ctx.accounts.ticket.min_result..=ctx.accounts.ticket.max_result).contains(&result) {
+            return Err(error!(SimpleRandomnessError::RandomResultOutOfBounds));
+        }
+
+        if ctx.accounts.ticket.settled_timestamp > 0 {
+            return Err(error!(SimpleRandomnessError::RequestAlreadySettled));
+        }
+
+        ctx.accounts.ticket.result = result;
+        ctx.accounts.ticket.settled_timestamp = Clock::get()?.unix_timestamp;
+
+        let user_won = ctx.accounts.ticket.result == ctx.accounts.ticket.guess;
+
+        // Release the ticket's liability and pay out the winner from the house vault
+        let max_payout = ctx.accounts.house.release(
+            ctx.accounts.ticket.wager,
+            ctx.accounts.ticket.min_result,
+            ctx.accounts.ticket.max_result,
+        )?;
+        let payout = if user_won { max_payout } else { 0 };
+        HouseState::pay_out(
+            &ctx.accounts.house.to_account_info(),
+            &ctx.accounts.authority,
+            payout,
+        )?;
+
+        emit!(UserGuessSettled {
+            user: ctx.accounts.ticket.key(),
+            user_guess: ctx.accounts.ticket.guess,
+            result: ctx.accounts.ticket.result,
+            user_won,
+            wager: ctx.accounts.ticket.wager,
+            payout,
+            request_timestamp: ctx.accounts.ticket.request_timestamp,
+            settled_timestamp: ctx.accounts.ticket.settled_timestamp
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_ticket(ctx: Context<CancelTicket>) -> anchor_lang::Result<()> {
+        // Release the liability of an unsettled ticket and refund its wager once the oracles can
+        // no longer fulfill it
+        if ctx.accounts.ticket.settled_timestamp == 0 {
+            let expiration_slot = ctx
+                .accounts
+                .switchboard_request
+                .active_request
+                .expiration_slot;
+            if Clock::get()?.slot <= expiration_slot {
+                return Err(error!(SimpleRandomnessError::RequestNotExpired));
+            }
+
+            let (wager, min_result, max_result) = (
+                ctx.accounts.ticket.wager,
+                ctx.accounts.ticket.min_result,
+                ctx.accounts.ticket.max_result,
+            );
+            ctx.accounts.house.release(wager, min_result, max_result)?;
+            HouseState::pay_out(
+                &ctx.accounts.house.to_account_info(),
+                &ctx.accounts.authority.to_account_info(),
+                wager,
+            )?;
+        }
+
+        // Anchor will handle closing the ticket because we used the 'close' attribute.
+
+        Ok(())
+    }
+
+    pub fn close(ctx: Context<Close>) -> anchor_lang::Result<()> {
+        // Release the liability of an unsettled guess and refund its wager once it has timed out
+        if ctx.accounts.user.request_timestamp > 0 && ctx.accounts.user.settled_timestamp == 0 {
+            if Clock::get()?.unix_timestamp - ctx.accounts.user.request_timestamp < REQUEST_TIMEOUT
+            {
+                return Err(error!(SimpleRandomnessError::RequestNotReady));
+            }
+
+            let (wager, min_result, max_result) = (
+                ctx.accounts.user.wager,
+                ctx.accounts.user.min_result,
+                ctx.accounts.user.max_result,
+            );
+            ctx.accounts.house.release(wager, min_result, max_result)?;
+            HouseState::pay_out(
+                &ctx.accounts.house.to_account_info(),
+                &ctx.accounts.authority.to_account_info(),
+                wager,
+            )?;
+        }
+
+        // Close the Switchboard request account and its associated token wallet, unless they were
+        // already closed by close_request or garbage collected.
+        if ctx.accounts.switchboard_request.owner == &SWITCHBOARD_ATTESTATION_PROGRAM_ID {
+            let close_ctx = FunctionRequestClose {
+                request: ctx.accounts.switchboard_request.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+                escrow: ctx.accounts.switchboard_request_escrow.to_account_info(),
+                function: ctx.accounts.switchboard_function.to_account_info(),
+                sol_dest: ctx.accounts.authority.to_account_info(),
+                escrow_dest: ctx.accounts.escrow_dest.to_account_info(),
+                state: ctx.accounts.switchboard_state.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            };
+            close_ctx.invoke_signed(
+                ctx.accounts.switchboard.clone(),
+                Some(true),
+                &[&[
+                    USER_SEED,
+                    ctx.accounts.authority.key().as_ref(),
+                    &[ctx.accounts.user.bump],
+                ]],
+            )?;
+        }
+
+        // Anchor will handle closing our program accounts because we used the 'close' attribute.
+
+        Ok(())
+    }
+
+    pub fn close_request(ctx: Context<CloseRequest>) -> anchor_lang::Result<()> {
+        // Close a previous Switchboard request account and its associated token wallet.
+        let close_ctx = FunctionRequestClose {
+            request: ctx.accounts.switchboard_request.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+            escrow: ctx.accounts.switchboard_request_escrow.to_account_info(),
+            function: ctx.accounts.switchboard_function.to_account_info(),
+            sol_dest: ctx.accounts.authority.to_account_info(),
+            escrow_dest: ctx.accounts.escrow_dest.to_account_info(),
+            state: ctx.accounts.switchboard_state.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        };
+        close_ctx.invoke_signed(
+            ctx.accounts.switchboard.clone(),
+            Some(true),
+            &[&[
+                USER_SEED,
+                ctx.accounts.authority.key().as_ref(),
+                &[ctx.accounts.user.bump],
+            ]],
+        )?;
+
+        Ok(())
+    }
 }
 
 #[event]
@@ -164,16 +730,100 @@ pub struct UserGuessSettled {
     pub user_guess: u32,
     pub result: u32,
     pub user_won: bool,
+    pub wager: u64,
+    pub payout: u64,
     pub request_timestamp: i64,
     pub settled_timestamp: i64,
 }
 
+#[derive(Accounts)]
+pub struct InitializeHouse<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<HouseState>(),
+        payer = payer,
+        seeds = [HOUSE_SEED],
+        bump
+    )]
+    pub house: Account<'info, HouseState>,
+    /// The program's upgrade authority, which becomes the account authorized to withdraw from the
+    /// house vault.
+    pub authority: Signer<'info>,
+
+    // UPGRADE AUTHORITY ACCOUNTS
+    // Only the upgrade authority can initialize the house so the deploy can't be front-run.
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::SuperSimpleRandomness>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ SimpleRandomnessError::InvalidAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxExposure<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+        has_one = authority,
+    )]
+    pub house: Account<'info, HouseState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetResultBounds<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+        has_one = authority,
+    )]
+    pub house: Account<'info, HouseState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawHouse<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+        has_one = authority,
+    )]
+    pub house: Account<'info, HouseState>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: the account receiving the withdrawn SOL.
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+}
+
 #[derive(Accounts)]
 pub struct Guess<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
 
     // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+    )]
+    pub house: Account<'info, HouseState>,
+
     #[account(
         init_if_needed,
         space = 8 + std::mem::size_of::<UserState>(),
@@ -183,7 +833,9 @@ pub struct Guess<'info> {
     )]
     pub user: Account<'info, UserState>,
 
-    /// CHECK:
+    /// CHECK: must sign for guess, or sign the relayed guess message for guess_relayed. Receives
+    /// the refund of a timed out guess.
+    #[account(mut)]
     pub authority: AccountInfo<'info>,
 
     // SWITCHBOARD ACCOUNTS
@@ -221,6 +873,158 @@ pub struct Guess<'info> {
     pub system_program: Program<'info, System>,
 }
 
+impl<'info> Guess<'info> {
+    /// Escrows the wager in the house vault and triggers a new Switchboard request for the users
+    /// guess.
+    pub fn place(
+        &mut self,
+        user_bump: u8,
+        guess: u32,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<()> {
+        self.house.validate_range(min_result, max_result)?;
+        if !(min_result..=max_result).contains(&guess) {
+            return Err(error!(SimpleRandomnessError::InvalidGuess));
+        }
+
+        if self.user.request_timestamp > 0
+            && self.user.settled_timestamp == 0
+            && Clock::get()?.unix_timestamp - self.user.request_timestamp < REQUEST_TIMEOUT
+        {
+            return Err(error!(SimpleRandomnessError::RequestNotReady));
+        }
+
+        // Release the liability of a previous guess that timed out without being settled and
+        // refund its wager. Its request can no longer settle once the user points at a new one.
+        if self.user.request_timestamp > 0 && self.user.settled_timestamp == 0 {
+            let (previous_wager, previous_min_result, previous_max_result) =
+                (self.user.wager, self.user.min_result, self.user.max_result);
+            self.house
+                .release(previous_wager, previous_min_result, previous_max_result)?;
+            HouseState::pay_out(
+                &self.house.to_account_info(),
+                &self.authority,
+                previous_wager,
+            )?;
+        }
+
+        // Make sure the open liability across all unsettled guesses stays within the exposure limit
+        let house = self.house.to_account_info();
+        let house_lamports = HouseState::available_lamports(&house)?;
+        self.house
+            .reserve(house_lamports, wager, min_result, max_result)?;
+
+        // Escrow the wager in the house vault
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                self.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: self.payer.to_account_info(),
+                    to: house,
+                },
+            ),
+            wager,
+        )?;
+
+        // Initialize user account if needed
+        if self.user.bump == 0 {
+            self.user.bump = user_bump;
+            self.user.authority = self.authority.key();
+        }
+
+        // Set new guess data
+        self.user.switchboard_request = self.switchboard_request.key();
+        self.user.guess = guess;
+        self.user.wager = wager;
+        self.user.min_result = min_result;
+        self.user.max_result = max_result;
+        self.user.result = 0;
+        self.user.request_timestamp = Clock::get()?.unix_timestamp;
+        self.user.settled_timestamp = 0;
+
+        // Trigger the Switchboard request
+        // This will instruct the off-chain oracles to execute your docker container and relay
+        // the result back to our program via the 'settle' instruction.
+
+        let request_params = format!(
+            "PID={},MIN_RESULT={},MAX_RESULT={},USER={},AUTHORITY={}",
+            crate::id(),
+            min_result,
+            max_result,
+            self.user.key(),
+            self.authority.key(),
+        );
+
+        // https://docs.rs/switchboard-solana/latest/switchboard_solana/attestation_program/instructions/request_init_and_trigger/index.html
+        let request_init_ctx = FunctionRequestInitAndTrigger {
+            request: self.switchboard_request.clone(),
+            authority: self.user.to_account_info(),
+            function: self.switchboard_function.to_account_info(),
+            function_authority: None,
+            escrow: self.switchboard_request_escrow.clone(),
+            mint: self.switchboard_mint.to_account_info(),
+            state: self.switchboard_state.to_account_info(),
+            attestation_queue: self.switchboard_attestation_queue.to_account_info(),
+            payer: self.payer.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            associated_token_program: self.associated_token_program.to_account_info(),
+        };
+        let user_authority_pubkey = self.authority.key();
+        let seeds = &[USER_SEED, user_authority_pubkey.as_ref(), &[self.user.bump]];
+
+        request_init_ctx.invoke_signed(
+            self.switchboard.clone(),
+            // bounty - optional fee to reward oracles for priority processing
+            // default: 0 lamports
+            None,
+            // slots_until_expiration - optional max number of slots the request can be processed in
+            // default: 2250 slots, ~ 15 min at 400 ms/slot
+            // minimum: 150 slots, ~ 1 min at 400 ms/slot
+            None,
+            // max_container_params_len - the length of the vec containing the container params
+            // default: 256 bytes
+            Some(512),
+            // container_params - the container params
+            // default: empty vec
+            Some(request_params.into_bytes()),
+            // garbage_collection_slot - the slot when the request can be closed by anyone and is considered dead
+            // default: None, only authority can close the request
+            Some(Clock::get()?.slot + GARBAGE_COLLECTION_SLOTS),
+            // valid_after_slot - schedule a request to execute in N slots
+            // default: 0 slots, valid immediately for oracles to process
+            None,
+            // signer seeds
+            &[seeds],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct GuessRelayed<'info> {
+    pub guess: Guess<'info>,
+
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        init_if_needed,
+        space = 8 + std::mem::size_of::<RelayState>(),
+        payer = guess.payer,
+        seeds = [RELAY_SEED, guess.authority.key().as_ref()],
+        bump
+    )]
+    pub relay: Account<'info, RelayState>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+    /// CHECK: the instructions sysvar, used to find the authority's ed25519 signature
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+}
+
 #[derive(Accounts)]
 pub struct Settle<'info> {
     // RANDOMNESS PROGRAM ACCOUNTS
@@ -242,6 +1046,231 @@ pub struct Settle<'info> {
     pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
     pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
     pub enclave_signer: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+    )]
+    pub house: Account<'info, HouseState>,
+    /// CHECK: the user's authority receives any winnings.
+    #[account(mut, address = user.authority)]
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(nonce: u64)]
+pub struct GuessWithTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+    )]
+    pub house: Account<'info, HouseState>,
+
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<GuessTicket>(),
+        payer = payer,
+        seeds = [USER_SEED, authority.key().as_ref(), nonce.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub ticket: Account<'info, GuessTicket>,
+
+    pub authority: Signer<'info>,
+
+    // SWITCHBOARD ACCOUNTS
+    /// CHECK:
+    #[account(executable, address = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
+    pub switchboard: AccountInfo<'info>,
+    /// CHECK: validated by Switchboard CPI
+    pub switchboard_state: AccountLoader<'info, AttestationProgramState>,
+    pub switchboard_attestation_queue: AccountLoader<'info, AttestationQueueAccountData>,
+    /// CHECK: validated by Switchboard CPI
+    #[account(mut)]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+    /// CHECK: validated by Switchboard CPI
+    #[account(
+        mut,
+        signer,
+        owner = system_program.key(),
+        constraint = switchboard_request.data_len() == 0 && switchboard_request.lamports() == 0
+      )]
+    pub switchboard_request: AccountInfo<'info>,
+    /// CHECK:
+    #[account(
+        mut,
+        owner = system_program.key(),
+        constraint = switchboard_request_escrow.data_len() == 0 && switchboard_request_escrow.lamports() == 0
+      )]
+    pub switchboard_request_escrow: AccountInfo<'info>,
+
+    // TOKEN ACCOUNTS
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub switchboard_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleTicket<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [USER_SEED, ticket.authority.as_ref(), ticket.nonce.to_le_bytes().as_ref()],
+        bump = ticket.bump,
+        has_one = switchboard_request,
+    )]
+    pub ticket: Account<'info, GuessTicket>,
+
+    // SWITCHBOARD ACCOUNTS
+    #[account(
+        constraint = switchboard_function.load()?.validate_request(
+            &switchboard_request,
+            &enclave_signer.to_account_info()
+        )?
+    )]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+    pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
+    pub enclave_signer: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+    )]
+    pub house: Account<'info, HouseState>,
+    /// CHECK: the ticket's authority receives any winnings.
+    #[account(mut, address = ticket.authority)]
+    pub authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelTicket<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        close = authority,
+        seeds = [USER_SEED, authority.key().as_ref(), ticket.nonce.to_le_bytes().as_ref()],
+        bump = ticket.bump,
+        has_one = switchboard_request,
+        has_one = authority,
+    )]
+    pub ticket: Account<'info, GuessTicket>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+    )]
+    pub house: Account<'info, HouseState>,
+
+    // SWITCHBOARD ACCOUNTS
+    pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
+}
+
+#[derive(Accounts)]
+pub struct Close<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        close = authority,
+        seeds = [USER_SEED, authority.key().as_ref()],
+        bump = user.bump,
+        has_one = switchboard_request,
+        has_one = authority,
+    )]
+    pub user: Account<'info, UserState>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub escrow_dest: Box<Account<'info, TokenAccount>>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.bump,
+    )]
+    pub house: Account<'info, HouseState>,
+
+    // SWITCHBOARD ACCOUNTS
+    /// CHECK:
+    #[account(executable, address = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
+    pub switchboard: AccountInfo<'info>,
+    pub switchboard_state: AccountLoader<'info, AttestationProgramState>,
+    /// CHECK: validated by Switchboard CPI
+    #[account(mut)]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+    /// CHECK: the user's current request, which may already be closed. Validated by Switchboard
+    /// CPI otherwise.
+    #[account(mut)]
+    pub switchboard_request: AccountInfo<'info>,
+    /// CHECK: validated by Switchboard CPI
+    #[account(mut)]
+    pub switchboard_request_escrow: AccountInfo<'info>,
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub switchboard_mint: Account<'info, Mint>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRequest<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        seeds = [USER_SEED, authority.key().as_ref()],
+        bump = user.bump,
+        has_one = authority,
+    )]
+    pub user: Account<'info, UserState>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub escrow_dest: Box<Account<'info, TokenAccount>>,
+
+    // SWITCHBOARD ACCOUNTS
+    /// CHECK:
+    #[account(executable, address = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
+    pub switchboard: AccountInfo<'info>,
+    pub switchboard_state: AccountLoader<'info, AttestationProgramState>,
+    /// CHECK: validated by Switchboard CPI
+    #[account(mut)]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+    // The current request can only be closed once its guess has settled.
+    #[account(
+        mut,
+        constraint = switchboard_request.authority == user.key()
+            @ SimpleRandomnessError::InvalidAuthority,
+        constraint = switchboard_request.key() != user.switchboard_request
+            || user.settled_timestamp > 0 @ SimpleRandomnessError::RequestNotReady,
+    )]
+    pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
+    /// CHECK: validated by Switchboard CPI
+    #[account(mut)]
+    pub switchboard_request_escrow: Box<Account<'info, TokenAccount>>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[error_code]
@@ -253,6 +1282,101 @@ pub enum SimpleRandomnessError {
     RequestNotReady,
     #[msg("Request already settled")]
     RequestAlreadySettled,
+    #[msg("Request has not expired yet")]
+    RequestNotExpired,
     #[msg("Random result is out-of-bounds")]
     RandomResultOutOfBounds,
+    #[msg("House edge must be between 0 and 10000 basis points")]
+    InvalidHouseEdge,
+    #[msg("House vault has insufficient funds to cover the payout")]
+    InsufficientHouseFunds,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Max exposure must be between 0 and 10000 basis points")]
+    InvalidExposureLimit,
+    #[msg("Guess would push the house's open liability above its exposure limit")]
+    ExposureLimitExceeded,
+    #[msg("Result range is invalid or outside the program bounds")]
+    InvalidResultRange,
+    #[msg("Guess is outside the result range")]
+    InvalidGuess,
+    #[msg("Relayed guess is missing the authority's ed25519 signature")]
+    InvalidRelaySignature,
+    #[msg("Relayed guess nonce does not match the user")]
+    InvalidRelayNonce,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lays out ed25519 program instruction data the way @solana/web3.js does: offsets, then the
+    // public key, signature, and message.
+    fn ed25519_data(signer: &Pubkey, message: &[u8], ix_index: u16) -> Vec<u8> {
+        let (public_key_offset, signature_offset, message_offset) = (16u16, 48u16, 112u16);
+        let mut data = vec![1, 0];
+        for value in [
+            signature_offset,
+            ix_index,
+            public_key_offset,
+            ix_index,
+            message_offset,
+            message.len() as u16,
+            ix_index,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[0u8; 64]);
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn test_house_liability() {
+        let mut house = HouseState {
+            bump: 0,
+            authority: Pubkey::default(),
+            house_edge_bps: MAX_HOUSE_EDGE_BPS,
+            outstanding_liability: 0,
+            max_exposure_bps: MAX_EXPOSURE_BPS,
+            min_result: MIN_RESULT,
+            max_result: MAX_RESULT,
+        };
+
+        // A 100% house edge never pays out, but a timed out guess still refunds its wager
+        house.reserve(1_000, 100, 1, 10).unwrap();
+        assert_eq!(house.outstanding_liability, 100);
+        assert_eq!(house.release(100, 1, 10).unwrap(), 0);
+        assert_eq!(house.outstanding_liability, 0);
+
+        // Otherwise the max payout is reserved
+        house.house_edge_bps = 0;
+        house.reserve(1_000, 100, 1, 10).unwrap();
+        assert_eq!(house.outstanding_liability, 1_000);
+        assert!(house.reserve(1_000, 1, 1, 1).is_err());
+        assert_eq!(house.release(100, 1, 10).unwrap(), 1_000);
+        assert_eq!(house.outstanding_liability, 0);
+    }
+
+    #[test]
+    fn test_verify_ed25519_data() {
+        let signer = Pubkey::new_unique();
+        let message = relayed_guess_message(&signer, 0, 1, 100, MIN_RESULT, MAX_RESULT);
+        let data = ed25519_data(&signer, &message, u16::MAX);
+        assert!(verify_ed25519_data(&data, &signer, &message).is_ok());
+
+        // A different signer, guess, or nonce must not verify
+        assert!(verify_ed25519_data(&data, &Pubkey::new_unique(), &message).is_err());
+        let other_guess = relayed_guess_message(&signer, 0, 2, 100, MIN_RESULT, MAX_RESULT);
+        assert!(verify_ed25519_data(&data, &signer, &other_guess).is_err());
+        let other_nonce = relayed_guess_message(&signer, 1, 1, 100, MIN_RESULT, MAX_RESULT);
+        assert!(verify_ed25519_data(&data, &signer, &other_nonce).is_err());
+
+        // The signed data must live in the ed25519 instruction itself
+        let data = ed25519_data(&signer, &message, 0);
+        assert!(verify_ed25519_data(&data, &signer, &message).is_err());
+
+        assert!(verify_ed25519_data(&[], &signer, &message).is_err());
+    }
 }
@@ -5,15 +5,47 @@
 // - initialize:  Initializes the program state with our Switchboard Function pubkey. The
 //                Switchboard Function points to our off-chain docker container and is
 //                responsible for generating the random value and calling our settle ixn.
+// - update_config:      Updates the request timeout, result range bounds, default oracle bounty,
+//                       request expiration, minimum request escrow balance, and protocol fee.
+// - set_function:       Points the program at a new Switchboard Function.
+// - propose_authority:  Proposes a new program authority. The transfer completes once the proposed
+//                       authority signs accept_authority.
+// - accept_authority:   Accepts a pending authority transfer.
+// - pause:              Pauses create_user and guess, or only the instructions in the given bitmask,
+//                       while settle and refunds keep working so in-flight rounds can drain.
+// - unpause:            Unpauses the program, or only the instructions in the given bitmask.
 // - create_user: Creates a new user who is allowed to request a new random value. This will
 //                create a new user owned Switchboard Function Request account with a CPI.
 // - guess:       Submits a new guess for the current user. This will make a CPI to the
 //                Switchboard Function Request account to trigger the off-chain docker container.
+//                Either the users authority or its session key must sign. A pending guess must be
+//                settled or cancelled first.
+// - create_session:     Registers a session key that can guess on the users behalf until it expires
+//                       or has wagered its allowance.
+// - revoke_session:     Revokes the users session key.
 // - settle:      This ixn will be invoked by the Switchboard oracle off-chain and will provide
 //                the random result to determine if the user won.
 // - close:       This ixn will close the Switchboard Request account for the given user, the requests
 //                escrow account, and the users randomness account. All SOL will be transferred to the
-//                users authority account.
+//                users authority account. A pending guess must be settled or cancelled first.
+// - fund_request_escrow: Wraps SOL into a users Switchboard request escrow so it can pay for
+//                       future guesses.
+// - cancel_guess:       Voids the current guess once its Switchboard request has expired and the
+//                       request timeout has passed without it being settled, and refunds the full
+//                       escrowed wager to the users authority.
+// - initialize_house:   Initializes the house vault which bankrolls the game, along with the share
+//                       token mint issued to liquidity providers.
+// - deposit_liquidity:  Deposits SOL into the house vault in exchange for share tokens priced at the
+//                       vaults net asset value.
+// - withdraw_liquidity: Burns share tokens in exchange for a pro-rata amount of the house vault, as
+//                       long as the remaining vault can cover all unsettled guesses.
+// - set_max_exposure:   Sets the share of the house vault that unsettled guesses can be exposed to.
+// - migrate_user:       Reallocates a user account created with the baseline layout and rewrites
+//                       it in the current layout.
+// - initialize_stats:   Initializes the program-wide statistics account updated by every settle.
+// - initialize_treasury: Initializes the treasury which collects the protocol fee on every settled
+//                       wager.
+// - withdraw_fees:      Withdraws collected protocol fees from the treasury to a destination.
 
 use switchboard_solana::prelude::*;
 
@@ -21,19 +53,76 @@ declare_id!("5bKuRru1qgEeQUXSAJvsXc8hZEGpCucEaaDQkmyd8j6v");
 
 pub const PROGRAM_SEED: &[u8] = b"SIMPLE_RANDOMNESS";
 pub const USER_SEED: &[u8] = b"RANDOMNESS_USER";
+pub const HOUSE_SEED: &[u8] = b"HOUSE_VAULT";
+pub const HOUSE_SHARE_MINT_SEED: &[u8] = b"HOUSE_SHARE_MINT";
+pub const STATS_SEED: &[u8] = b"GLOBAL_STATS";
+pub const TREASURY_SEED: &[u8] = b"TREASURY";
+
+/// Pauses the create_user instruction when set in `ProgramState::paused_ixs`.
+pub const PAUSE_CREATE_USER: u8 = 1 << 0;
+/// Pauses the guess instruction when set in `ProgramState::paused_ixs`.
+pub const PAUSE_GUESS: u8 = 1 << 1;
 
 // [MIN_RESULT, MAX_RESULT]
-/// The minimum guess that can be submitted, inclusive.
+/// The default lower bound for any guess' result range, inclusive.
 pub const MIN_RESULT: u32 = 1;
-/// The maximum guess that can be submitted, inclusive.
+/// The default upper bound for any guess' result range, inclusive.
 pub const MAX_RESULT: u32 = 10;
 
-/// The minimum amount of time before a user can re-guess if the previous guess hasnt settled.
-pub const REQUEST_TIMEOUT: i64 = 60;
+/// The default minimum amount of time before a user can cancel a guess that hasnt settled,
+/// matching the default Switchboard request expiration.
+pub const REQUEST_TIMEOUT: i64 = 900;
+
+/// The lowest configurable request timeout, matching the minimum Switchboard request expiration
+/// of 150 slots, ~ 1 min at 400 ms/slot.
+pub const MIN_REQUEST_TIMEOUT: i64 = 60;
+
+/// The default number of slots a Switchboard request can be processed in, ~ 15 min at 400 ms/slot.
+pub const DEFAULT_SLOTS_UNTIL_EXPIRATION: u64 = 2250;
+
+/// The minimum number of slots a Switchboard request can be processed in, ~ 1 min at 400 ms/slot.
+pub const MIN_SLOTS_UNTIL_EXPIRATION: u64 = 150;
+
+/// The approximate duration of a slot, used to convert request expirations to seconds.
+pub const SLOT_DURATION_MS: u64 = 400;
+
+/// The highest configurable result range upper bound, so the Switchboard Function's inclusive
+/// `max_result - min_result + 1` window always fits in a u32.
+pub const MAX_RESULT_BOUND: u32 = u32::MAX - 1;
+
+/// The maximum house edge, in basis points, that can be configured.
+pub const MAX_HOUSE_EDGE_BPS: u16 = 10_000;
+
+/// The maximum protocol fee, in basis points of each wager, that can be configured.
+pub const MAX_FEE_BPS: u16 = 10_000;
+
+/// The maximum amount of time, in seconds, a session key can be valid for.
+pub const MAX_SESSION_DURATION: i64 = 7 * 24 * 60 * 60;
+
+/// The maximum share of the house vault, in basis points, that unsettled guesses can be exposed to.
+pub const MAX_EXPOSURE_BPS: u16 = 10_000;
+
+/// The number of decimals used by the house share token mint.
+pub const HOUSE_SHARE_DECIMALS: u8 = 9;
+
+/// Virtual shares and lamports added to the house vault when pricing shares, so a donation to an
+/// empty vault can't inflate the share price enough to round a later deposit down to zero shares.
+pub const VIRTUAL_LIQUIDITY: u64 = 1_000_000;
+
+/// The size of a user account, including the discriminator, created with the baseline layout.
+pub const BASELINE_USER_STATE_LEN: usize = 8 + std::mem::size_of::<BaselineUserState>();
+
+/// The number of settled rounds kept in each users guess history.
+pub const USER_HISTORY_LEN: usize = 16;
+
+/// The number of buckets in the global result histogram, one per result in
+/// `[MIN_RESULT, MAX_RESULT]`.
+pub const RESULT_HISTOGRAM_LEN: usize = (MAX_RESULT - MIN_RESULT + 1) as usize;
 
 #[program]
 pub mod switchboard_randomness_callback {
     use super::*;
+    use switchboard_solana::wrap_native;
 
     pub fn initialize(ctx: Context<Initialize>) -> anchor_lang::Result<()> {
         let mut program_state = ctx.accounts.program_state.load_init()?;
@@ -42,13 +131,134 @@ pub mod switchboard_randomness_callback {
         program_state.bump = ctx.bumps.program_state;
         program_state.authority = *ctx.accounts.authority.key;
         program_state.switchboard_function = ctx.accounts.switchboard_function.key();
+        program_state.min_result = MIN_RESULT;
+        program_state.max_result = MAX_RESULT;
+        program_state.request_timeout = REQUEST_TIMEOUT;
+        program_state.slots_until_expiration = DEFAULT_SLOTS_UNTIL_EXPIRATION;
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        request_timeout: Option<i64>,
+        min_result: Option<u32>,
+        max_result: Option<u32>,
+        default_bounty: Option<u64>,
+        slots_until_expiration: Option<u64>,
+        min_request_escrow: Option<u64>,
+        fee_bps: Option<u16>,
+    ) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+
+        let min_result = min_result.unwrap_or(program_state.min_result);
+        let max_result = max_result.unwrap_or(program_state.max_result);
+        if min_result > max_result || max_result == 0 || max_result > MAX_RESULT_BOUND {
+            return Err(error!(SimpleRandomnessError::InvalidResultRange));
+        }
+
+        let slots_until_expiration =
+            slots_until_expiration.unwrap_or(program_state.slots_until_expiration);
+        if slots_until_expiration < MIN_SLOTS_UNTIL_EXPIRATION {
+            return Err(error!(SimpleRandomnessError::InvalidRequestExpiration));
+        }
+
+        // A guess can't be cancelled before its request could have expired
+        let request_timeout = request_timeout.unwrap_or(program_state.request_timeout);
+        let expiration_seconds = slots_until_expiration.saturating_mul(SLOT_DURATION_MS) / 1_000;
+        if request_timeout < MIN_REQUEST_TIMEOUT
+            || u64::try_from(request_timeout).unwrap_or(0) < expiration_seconds
+        {
+            return Err(error!(SimpleRandomnessError::InvalidRequestTimeout));
+        }
+
+        let fee_bps = fee_bps.unwrap_or(program_state.fee_bps);
+        if fee_bps > MAX_FEE_BPS {
+            return Err(error!(SimpleRandomnessError::InvalidFee));
+        }
+
+        program_state.request_timeout = request_timeout;
+        program_state.min_result = min_result;
+        program_state.max_result = max_result;
+        program_state.default_bounty = default_bounty.unwrap_or(program_state.default_bounty);
+        program_state.slots_until_expiration = slots_until_expiration;
+        program_state.min_request_escrow =
+            min_request_escrow.unwrap_or(program_state.min_request_escrow);
+        program_state.fee_bps = fee_bps;
+
+        Ok(())
+    }
+
+    pub fn set_function(ctx: Context<SetFunction>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        let old_function = program_state.switchboard_function;
+        program_state.switchboard_function = ctx.accounts.switchboard_function.key();
+
+        emit!(SwitchboardFunctionUpdated {
+            old_function,
+            new_function: program_state.switchboard_function,
+        });
+
+        Ok(())
+    }
+
+    pub fn propose_authority(
+        ctx: Context<ProposeAuthority>,
+        pending_authority: Pubkey,
+    ) -> anchor_lang::Result<()> {
+        ctx.accounts.program_state.load_mut()?.pending_authority = pending_authority;
+
+        emit!(AuthorityProposed {
+            authority: ctx.accounts.authority.key(),
+            pending_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        let old_authority = program_state.authority;
+        program_state.authority = ctx.accounts.pending_authority.key();
+        program_state.pending_authority = Pubkey::default();
+
+        emit!(AuthorityAccepted {
+            old_authority,
+            new_authority: program_state.authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn pause(ctx: Context<SetPaused>, paused_ixs: Option<u8>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        match paused_ixs {
+            Some(paused_ixs) => program_state.paused_ixs |= paused_ixs,
+            None => program_state.paused = true,
+        }
+
+        Ok(())
+    }
+
+    pub fn unpause(ctx: Context<SetPaused>, paused_ixs: Option<u8>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        match paused_ixs {
+            Some(paused_ixs) => program_state.paused_ixs &= !paused_ixs,
+            None => {
+                program_state.paused = false;
+                program_state.paused_ixs = 0;
+            }
+        }
 
         Ok(())
     }
 
     pub fn create_user(ctx: Context<CreateUser>) -> anchor_lang::prelude::Result<()> {
         // Verify this exists
-        let _program_state = ctx.accounts.program_state.load()?;
+        let program_state = ctx.accounts.program_state.load()?;
+        program_state.assert_not_paused(PAUSE_CREATE_USER)?;
+        let (min_result, max_result) = (program_state.min_result, program_state.max_result);
 
         let user_key = ctx.accounts.user.key();
 
@@ -68,11 +278,14 @@ pub mod switchboard_randomness_callback {
             associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
         };
         let request_params = format!(
-            "PID={},MIN_RESULT={},MAX_RESULT={},USER={}",
+            "PID={},MIN_RESULT={},MAX_RESULT={},USER={},AUTHORITY={},STATS={},PROGRAM_STATE={}",
             crate::id(),
-            MIN_RESULT,
-            MAX_RESULT,
+            min_result,
+            max_result,
             user_key,
+            ctx.accounts.authority.key(),
+            ctx.accounts.stats.key(),
+            ctx.accounts.program_state.key(),
         );
         request_init_ctx.invoke(
             ctx.accounts.switchboard.clone(),
@@ -96,15 +309,105 @@ pub mod switchboard_randomness_callback {
         Ok(())
     }
 
-    pub fn guess(ctx: Context<Guess>, guess: u32) -> anchor_lang::Result<()> {
+    pub fn create_session(
+        ctx: Context<SetSession>,
+        session_key: Pubkey,
+        duration: i64,
+        allowance: u64,
+    ) -> anchor_lang::Result<()> {
+        if duration <= 0 || duration > MAX_SESSION_DURATION {
+            return Err(error!(SimpleRandomnessError::InvalidSessionDuration));
+        }
+
+        let mut user = ctx.accounts.user.load_mut()?;
+        user.session_key = session_key;
+        user.session_expiration = Clock::get()?.unix_timestamp + duration;
+        user.session_allowance = allowance;
+
+        emit!(SessionCreated {
+            user: ctx.accounts.user.key(),
+            session_key,
+            expiration: user.session_expiration,
+            allowance,
+        });
+
+        Ok(())
+    }
+
+    pub fn revoke_session(ctx: Context<SetSession>) -> anchor_lang::Result<()> {
+        let mut user = ctx.accounts.user.load_mut()?;
+        let session_key = user.session_key;
+        user.session_key = Pubkey::default();
+        user.session_expiration = 0;
+        user.session_allowance = 0;
+
+        emit!(SessionRevoked {
+            user: ctx.accounts.user.key(),
+            session_key,
+        });
+
+        Ok(())
+    }
+
+    pub fn guess(
+        ctx: Context<Guess>,
+        guess: u32,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+        bounty: Option<u64>,
+    ) -> anchor_lang::Result<()> {
+        let program_state = ctx.accounts.program_state.load()?;
+        program_state.assert_not_paused(PAUSE_GUESS)?;
+        program_state.validate_range(min_result, max_result)?;
+        // A priority bounty is paid by the payer when the request is triggered
+        let bounty = bounty.unwrap_or(program_state.default_bounty);
+        let slots_until_expiration = program_state.slots_until_expiration;
+        let min_request_escrow = program_state
+            .min_request_escrow
+            .checked_add(bounty)
+            .ok_or(error!(SimpleRandomnessError::MathOverflow))?;
+        let fee = program_state.fee(wager)?;
+        drop(program_state);
+
+        if !(min_result..=max_result).contains(&guess) {
+            return Err(error!(SimpleRandomnessError::InvalidGuess));
+        }
+
+        ctx.accounts.user.load_mut()?.authorize_guess(
+            ctx.accounts.signer.key(),
+            wager,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        // A pending round must be settled, or cancelled once its request expires, before the
+        // request can be triggered again
         if ctx.accounts.user.load()?.request_timestamp > 0
             && ctx.accounts.user.load()?.settled_timestamp == 0
-            && Clock::get()?.unix_timestamp - ctx.accounts.user.load()?.request_timestamp
-                < REQUEST_TIMEOUT
         {
             return Err(error!(SimpleRandomnessError::RequestNotReady));
         }
 
+        // Make sure the open liability across all unsettled guesses stays within the exposure limit
+        let house_lamports = HouseVault::available_lamports(&ctx.accounts.house.to_account_info())?;
+        ctx.accounts
+            .house
+            .load_mut()?
+            .reserve(house_lamports, wager, min_result, max_result)?;
+
+        // Escrow the wager in the house vault. The protocol fee is only sent to the treasury once
+        // the guess settles, so a cancelled guess is refunded in full.
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: ctx.accounts.house.to_account_info(),
+                },
+            ),
+            wager,
+        )?;
+
         // NOTE: See FunctionRequestInitAndTrigger to create a new request each time and trigger it.
         // https://docs.rs/switchboard-solana/latest/switchboard_solana/attestation_program/instructions/request_init_and_trigger/index.html
 
@@ -130,15 +433,62 @@ pub mod switchboard_randomness_callback {
             &[ctx.accounts.user.load()?.bump],
         ];
 
+        // Update the request's container params with this guess' result range before triggering it.
+        let request_params = format!(
+            "PID={},MIN_RESULT={},MAX_RESULT={},USER={},AUTHORITY={},STATS={},PROGRAM_STATE={}",
+            crate::id(),
+            min_result,
+            max_result,
+            ctx.accounts.user.key(),
+            user_authority_pubkey,
+            ctx.accounts.stats.key(),
+            ctx.accounts.program_state.key(),
+        );
+        let request_set_config_ctx = FunctionRequestSetConfig {
+            request: ctx.accounts.switchboard_request.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        request_set_config_ctx.invoke_signed(
+            ctx.accounts.switchboard.clone(),
+            // container_params - the new container params
+            request_params.into_bytes(),
+            // append_container_params - whether to append to or replace the existing params
+            false,
+            &[seeds],
+        )?;
+
+        // Top up the request escrow from the payer so the oracles can be paid for this guess
+        let escrow_balance = ctx.accounts.switchboard_request_escrow.amount;
+        if escrow_balance < min_request_escrow {
+            let top_up = min_request_escrow - escrow_balance;
+            if ctx.accounts.payer.lamports() < top_up {
+                return Err(error!(SimpleRandomnessError::InsufficientRequestEscrow));
+            }
+
+            wrap_native(
+                &ctx.accounts.system_program.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.switchboard_request_escrow,
+                &ctx.accounts.payer.to_account_info(),
+                &[seeds],
+                top_up,
+            )?;
+
+            ctx.accounts.switchboard_request_escrow.reload()?;
+            if ctx.accounts.switchboard_request_escrow.amount < min_request_escrow {
+                return Err(error!(SimpleRandomnessError::InsufficientRequestEscrow));
+            }
+        }
+
         request_trigger_ctx.invoke_signed(
             ctx.accounts.switchboard.clone(),
             // bounty - optional fee to reward oracles for priority processing
             // default: 0 lamports
-            None,
+            Some(bounty),
             // slots_until_expiration - optional max number of slots the request can be processed in
             // default: 2250 slots, ~ 15 min at 400 ms/slot
             // minimum: 150 slots, ~ 1 min at 400 ms/slot
-            None,
+            Some(slots_until_expiration),
             // valid_after_slot - schedule a request to execute in N slots
             // default: 0 slots, valid immediately for oracles to process
             None,
@@ -149,6 +499,12 @@ pub mod switchboard_randomness_callback {
 
         // Set new guess data
         user.guess = guess;
+        user.wager = wager;
+        user.fee = fee;
+        user.min_result = min_result;
+        user.max_result = max_result;
+        user.bounty = bounty;
+        user.slots_until_expiration = slots_until_expiration;
         user.result = 0;
         user.request_timestamp = Clock::get()?.unix_timestamp;
         user.settled_timestamp = 0;
@@ -157,11 +513,11 @@ pub mod switchboard_randomness_callback {
     }
 
     pub fn settle(ctx: Context<Settle>, result: u32) -> anchor_lang::Result<()> {
-        if !(MIN_RESULT..MAX_RESULT).contains(&result) {
+        let mut user = ctx.accounts.user.load_mut()?;
+        if !(user.min_result..=user.max_result).contains(&result) {
             return Err(error!(SimpleRandomnessError::RandomResultOutOfBounds));
         }
 
-        let mut user = ctx.accounts.user.load_mut()?;
         if user.settled_timestamp > 0 {
             return Err(error!(SimpleRandomnessError::RequestAlreadySettled));
         }
@@ -169,13 +525,55 @@ pub mod switchboard_randomness_callback {
         user.result = result;
         user.settled_timestamp = Clock::get()?.unix_timestamp;
 
-        // TODO: handle any custom game logic here
+        let user_won = user.result == user.guess;
+        let (guess, settled_slot) = (user.guess, Clock::get()?.slot);
+        user.push_history(GuessRecord {
+            guess,
+            result,
+            won: user_won,
+            settled_slot,
+        });
+
+        // Release the guess' liability and pay out the winner from the house vault
+        let max_payout =
+            ctx.accounts
+                .house
+                .load_mut()?
+                .release(user.wager, user.min_result, user.max_result)?;
+        let payout = if user_won { max_payout } else { 0 };
+        HouseVault::pay_out(
+            &ctx.accounts.house.to_account_info(),
+            &ctx.accounts.authority,
+            payout,
+        )?;
+
+        // Collect the protocol fee now that the round has been fulfilled
+        HouseVault::pay_out(
+            &ctx.accounts.house.to_account_info(),
+            &ctx.accounts.treasury.to_account_info(),
+            user.fee,
+        )?;
+        if user.fee > 0 {
+            let mut treasury = ctx.accounts.treasury.load_mut()?;
+            treasury.total_collected = treasury.total_collected.saturating_add(user.fee);
+        }
+
+        ctx.accounts.stats.load_mut()?.record(
+            result,
+            user.min_result,
+            user.max_result,
+            user.wager,
+            payout,
+            user_won,
+        );
 
         emit!(UserGuessSettled {
             user: ctx.accounts.user.key(),
             user_guess: user.guess,
             result: user.result,
-            user_won: user.result == user.guess,
+            user_won,
+            wager: user.wager,
+            payout,
             request_timestamp: user.request_timestamp,
             settled_timestamp: user.settled_timestamp
         });
@@ -183,6 +581,78 @@ pub mod switchboard_randomness_callback {
         Ok(())
     }
 
+    pub fn fund_request_escrow(
+        ctx: Context<FundRequestEscrow>,
+        amount: u64,
+    ) -> anchor_lang::Result<()> {
+        if amount == 0 {
+            return Err(error!(SimpleRandomnessError::InvalidAmount));
+        }
+
+        let user_authority_pubkey = ctx.accounts.user.load()?.authority;
+        wrap_native(
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.switchboard_request_escrow,
+            &ctx.accounts.payer.to_account_info(),
+            &[&[
+                USER_SEED,
+                user_authority_pubkey.as_ref(),
+                &[ctx.accounts.user.load()?.bump],
+            ]],
+            amount,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn cancel_guess(ctx: Context<CancelGuess>) -> anchor_lang::Result<()> {
+        let mut user = ctx.accounts.user.load_mut()?;
+        if user.request_timestamp == 0 || user.settled_timestamp > 0 {
+            return Err(error!(SimpleRandomnessError::RequestAlreadySettled));
+        }
+
+        // Only void the round once the oracles can no longer fulfill it
+        let expiration_slot = ctx
+            .accounts
+            .switchboard_request
+            .active_request
+            .expiration_slot;
+        let request_timeout = ctx.accounts.program_state.load()?.request_timeout;
+        if Clock::get()?.slot <= expiration_slot
+            || Clock::get()?.unix_timestamp - user.request_timestamp < request_timeout
+        {
+            return Err(error!(SimpleRandomnessError::RequestNotExpired));
+        }
+
+        user.result = 0;
+        user.settled_timestamp = Clock::get()?.unix_timestamp;
+
+        // Release the guess' liability and refund the escrowed wager from the house vault. The
+        // protocol fee is only collected on settle, so the full wager is refunded.
+        ctx.accounts
+            .house
+            .load_mut()?
+            .release(user.wager, user.min_result, user.max_result)?;
+
+        let refund = user.wager;
+        HouseVault::pay_out(
+            &ctx.accounts.house.to_account_info(),
+            &ctx.accounts.authority.to_account_info(),
+            refund,
+        )?;
+
+        emit!(UserGuessCancelled {
+            user: ctx.accounts.user.key(),
+            user_guess: user.guess,
+            refund,
+            request_timestamp: user.request_timestamp,
+            cancelled_timestamp: user.settled_timestamp
+        });
+
+        Ok(())
+    }
+
     pub fn close(ctx: Context<Close>) -> anchor_lang::Result<()> {
         let user_bump = ctx.accounts.user.load()?.bump;
 
@@ -212,6 +682,203 @@ pub mod switchboard_randomness_callback {
 
         Ok(())
     }
+
+    pub fn migrate_user(ctx: Context<MigrateUser>) -> anchor_lang::Result<()> {
+        let user = ctx.accounts.user.to_account_info();
+        if user.try_borrow_data()?[..8] != UserState::discriminator() {
+            return Err(error!(ErrorCode::AccountDiscriminatorMismatch));
+        }
+
+        let new_len = 8 + std::mem::size_of::<UserState>();
+        if user.data_len() >= new_len {
+            return Ok(());
+        }
+        if user.data_len() != BASELINE_USER_STATE_LEN {
+            return Err(error!(ErrorCode::AccountDidNotDeserialize));
+        }
+
+        // Fund the larger account so it stays rent exempt
+        let rent_exempt_lamports = Rent::get()?.minimum_balance(new_len);
+        let top_up = rent_exempt_lamports.saturating_sub(user.lamports());
+        if top_up > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: user.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+
+        // Packed zero copy structs don't keep their field order, so every field has to be moved
+        // from the baseline layout rather than only growing the account
+        user.realloc(new_len, true)?;
+        UserState::migrate_baseline(&mut user.try_borrow_mut_data()?);
+
+        Ok(())
+    }
+
+    pub fn initialize_stats(ctx: Context<InitializeStats>) -> anchor_lang::Result<()> {
+        let mut stats = ctx.accounts.stats.load_init()?;
+        stats.bump = ctx.bumps.stats;
+
+        Ok(())
+    }
+
+    pub fn initialize_house(
+        ctx: Context<InitializeHouse>,
+        house_edge_bps: u16,
+        max_exposure_bps: u16,
+    ) -> anchor_lang::Result<()> {
+        if house_edge_bps > MAX_HOUSE_EDGE_BPS {
+            return Err(error!(SimpleRandomnessError::InvalidHouseEdge));
+        }
+        if max_exposure_bps > MAX_EXPOSURE_BPS {
+            return Err(error!(SimpleRandomnessError::InvalidExposureLimit));
+        }
+
+        let mut house = ctx.accounts.house.load_init()?;
+        house.bump = ctx.bumps.house;
+        house.share_mint = ctx.accounts.share_mint.key();
+        house.house_edge_bps = house_edge_bps;
+        house.max_exposure_bps = max_exposure_bps;
+
+        Ok(())
+    }
+
+    pub fn set_max_exposure(
+        ctx: Context<SetMaxExposure>,
+        max_exposure_bps: u16,
+    ) -> anchor_lang::Result<()> {
+        if max_exposure_bps > MAX_EXPOSURE_BPS {
+            return Err(error!(SimpleRandomnessError::InvalidExposureLimit));
+        }
+
+        ctx.accounts.house.load_mut()?.max_exposure_bps = max_exposure_bps;
+
+        Ok(())
+    }
+
+    pub fn deposit_liquidity(
+        ctx: Context<DepositLiquidity>,
+        amount: u64,
+    ) -> anchor_lang::Result<()> {
+        if amount == 0 {
+            return Err(error!(SimpleRandomnessError::InvalidAmount));
+        }
+
+        // Price the new shares against the vault before the deposit lands
+        let nav = HouseVault::available_lamports(&ctx.accounts.house.to_account_info())?;
+        let shares = HouseVault::shares_for_deposit(amount, nav, ctx.accounts.share_mint.supply)?;
+        if shares == 0 {
+            return Err(error!(SimpleRandomnessError::InvalidAmount));
+        }
+
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.provider.to_account_info(),
+                    to: ctx.accounts.house.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let house_bump = ctx.accounts.house.load()?.bump;
+        anchor_spl::token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                anchor_spl::token::MintTo {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    to: ctx.accounts.provider_shares.to_account_info(),
+                    authority: ctx.accounts.house.to_account_info(),
+                },
+                &[&[HOUSE_SEED, &[house_bump]]],
+            ),
+            shares,
+        )?;
+
+        emit!(HouseLiquidityDeposited {
+            provider: ctx.accounts.provider.key(),
+            amount,
+            shares,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_liquidity(
+        ctx: Context<WithdrawLiquidity>,
+        shares: u64,
+    ) -> anchor_lang::Result<()> {
+        if shares == 0 {
+            return Err(error!(SimpleRandomnessError::InvalidAmount));
+        }
+
+        let house_info = ctx.accounts.house.to_account_info();
+        let nav = HouseVault::available_lamports(&house_info)?;
+        let amount = HouseVault::amount_for_shares(shares, nav, ctx.accounts.share_mint.supply)?;
+
+        // The remaining vault must still cover every unsettled guess
+        if nav - amount < ctx.accounts.house.load()?.outstanding_liability {
+            return Err(error!(SimpleRandomnessError::InsufficientHouseLiquidity));
+        }
+
+        anchor_spl::token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                anchor_spl::token::Burn {
+                    mint: ctx.accounts.share_mint.to_account_info(),
+                    from: ctx.accounts.provider_shares.to_account_info(),
+                    authority: ctx.accounts.provider.to_account_info(),
+                },
+            ),
+            shares,
+        )?;
+
+        **house_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.provider.try_borrow_mut_lamports()? += amount;
+
+        emit!(HouseLiquidityWithdrawn {
+            provider: ctx.accounts.provider.key(),
+            amount,
+            shares,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> anchor_lang::Result<()> {
+        let mut treasury = ctx.accounts.treasury.load_init()?;
+        treasury.bump = ctx.bumps.treasury;
+
+        Ok(())
+    }
+
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> anchor_lang::Result<()> {
+        if amount == 0 {
+            return Err(error!(SimpleRandomnessError::InvalidAmount));
+        }
+
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        if HouseVault::available_lamports(&treasury_info)? < amount {
+            return Err(error!(SimpleRandomnessError::InsufficientFees));
+        }
+
+        **treasury_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.destination.try_borrow_mut_lamports()? += amount;
+
+        emit!(FeesWithdrawn {
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+
+        Ok(())
+    }
 }
 
 #[event]
@@ -220,11 +887,76 @@ pub struct UserGuessSettled {
     pub user_guess: u32,
     pub result: u32,
     pub user_won: bool,
+    pub wager: u64,
+    pub payout: u64,
     pub request_timestamp: i64,
     pub settled_timestamp: i64,
 }
 
+#[event]
+pub struct UserGuessCancelled {
+    pub user: Pubkey,
+    pub user_guess: u32,
+    pub refund: u64,
+    pub request_timestamp: i64,
+    pub cancelled_timestamp: i64,
+}
+
+#[event]
+pub struct SwitchboardFunctionUpdated {
+    pub old_function: Pubkey,
+    pub new_function: Pubkey,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityAccepted {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct HouseLiquidityDeposited {
+    pub provider: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct HouseLiquidityWithdrawn {
+    pub provider: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct SessionCreated {
+    pub user: Pubkey,
+    pub session_key: Pubkey,
+    pub expiration: i64,
+    pub allowance: u64,
+}
+
+#[event]
+pub struct SessionRevoked {
+    pub user: Pubkey,
+    pub session_key: Pubkey,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
 /// Represents the global state of the program.
+/// Grown past its original layout with no migration path, so a program deployed with the original
+/// layout must be redeployed under a new program ID and re-initialized.
 #[account(zero_copy(unsafe))]
 pub struct ProgramState {
     /// PDA bump seed.
@@ -233,6 +965,52 @@ pub struct ProgramState {
     pub authority: Pubkey,
     /// Switchboard Function pubkey.
     pub switchboard_function: Pubkey,
+    /// The lowest result range lower bound a guess can request, inclusive.
+    pub min_result: u32,
+    /// The highest result range upper bound a guess can request, inclusive.
+    pub max_result: u32,
+    /// Account proposed to take over as authority, or the default pubkey if none.
+    pub pending_authority: Pubkey,
+    /// Pauses every pausable instruction when set.
+    pub paused: bool,
+    /// Bitmask of individually paused instructions, see the `PAUSE_*` constants.
+    pub paused_ixs: u8,
+    /// The minimum amount of time before a user can cancel a guess that hasnt settled.
+    pub request_timeout: i64,
+    /// The bounty, in lamports, paid to oracles for each guess that doesnt set its own.
+    pub default_bounty: u64,
+    /// The number of slots each guess' Switchboard request can be processed in.
+    pub slots_until_expiration: u64,
+    /// The wrapped SOL balance, on top of the bounty, that guess tops up a users request escrow to.
+    pub min_request_escrow: u64,
+    /// The protocol fee, in basis points, taken from each settled wager and sent to the treasury.
+    pub fee_bps: u16,
+}
+
+impl ProgramState {
+    /// Returns the protocol fee taken from the given wager.
+    pub fn fee(&self, wager: u64) -> anchor_lang::Result<u64> {
+        u64::try_from(u128::from(wager) * u128::from(self.fee_bps) / u128::from(MAX_FEE_BPS))
+            .map_err(|_| error!(SimpleRandomnessError::MathOverflow))
+    }
+
+    /// Fails if the program, or the instruction with the given `PAUSE_*` bit, is paused.
+    pub fn assert_not_paused(&self, ix: u8) -> anchor_lang::Result<()> {
+        if self.paused || self.paused_ixs & ix != 0 {
+            return Err(error!(SimpleRandomnessError::ProgramPaused));
+        }
+
+        Ok(())
+    }
+
+    /// Verifies a guess' result range is well formed and within the program-wide bounds.
+    pub fn validate_range(&self, min_result: u32, max_result: u32) -> anchor_lang::Result<()> {
+        if min_result > max_result || min_result < self.min_result || max_result > self.max_result {
+            return Err(error!(SimpleRandomnessError::InvalidResultRange));
+        }
+
+        Ok(())
+    }
 }
 
 /// Represents a users config.
@@ -247,15 +1025,300 @@ pub struct UserState {
     pub switchboard_request: Pubkey,
     /// The current users guess.
     pub guess: u32,
+    /// The amount of lamports wagered on the current guess.
+    pub wager: u64,
+    /// The lower bound of the current guess' result range, inclusive.
+    pub min_result: u32,
+    /// The upper bound of the current guess' result range, inclusive.
+    pub max_result: u32,
     /// The Switchboard Function result.
     pub result: u32,
     /// The timestamp when the current guess was placed.
     pub request_timestamp: i64,
-    /// The timestamp when the request was settled.
+    /// The timestamp when the request was settled or cancelled.
     pub settled_timestamp: i64,
+    /// The index in `history` the next settled round will be written to.
+    pub history_head: u32,
+    /// The number of settled rounds stored in `history`, up to `USER_HISTORY_LEN`.
+    pub history_len: u32,
+    /// Ring buffer of the most recently settled rounds.
+    pub history: [GuessRecord; USER_HISTORY_LEN],
+    /// The bounty, in lamports, paid to oracles for the current guess.
+    pub bounty: u64,
+    /// The number of slots the current guess' Switchboard request can be processed in.
+    pub slots_until_expiration: u64,
+    /// The protocol fee taken from the current guess' wager once it settles.
+    pub fee: u64,
+    /// Key allowed to guess on the authority's behalf, or the default pubkey if none.
+    pub session_key: Pubkey,
+    /// The timestamp after which the session key can no longer guess.
+    pub session_expiration: i64,
+    /// The amount of lamports the session key can still wager.
+    pub session_allowance: u64,
 }
 
-#[derive(Accounts)]
+impl UserState {
+    /// Rewrites user account data, already reallocated to the current size, from the baseline
+    /// layout. Baseline guesses always used the `[MIN_RESULT, MAX_RESULT]` range.
+    pub fn migrate_baseline(data: &mut [u8]) {
+        use anchor_lang::__private::bytemuck;
+
+        let baseline: BaselineUserState = *bytemuck::from_bytes(&data[8..BASELINE_USER_STATE_LEN]);
+        let user: &mut UserState = bytemuck::from_bytes_mut(&mut data[8..]);
+        *user = bytemuck::Zeroable::zeroed();
+        user.bump = baseline.bump;
+        user.authority = baseline.authority;
+        user.switchboard_request = baseline.switchboard_request;
+        user.guess = baseline.guess;
+        user.min_result = MIN_RESULT;
+        user.max_result = MAX_RESULT;
+        user.result = baseline.result;
+        user.request_timestamp = baseline.request_timestamp;
+        user.settled_timestamp = baseline.settled_timestamp;
+    }
+
+    /// Records a settled round, overwriting the oldest round once the history is full.
+    pub fn push_history(&mut self, record: GuessRecord) {
+        let head = self.history_head as usize % USER_HISTORY_LEN;
+        self.history[head] = record;
+        self.history_head = ((head + 1) % USER_HISTORY_LEN) as u32;
+        self.history_len = (self.history_len + 1).min(USER_HISTORY_LEN as u32);
+    }
+
+    /// Fails unless the signer is the authority or an unexpired session key with enough allowance
+    /// left for the wager, which is then deducted from the allowance.
+    pub fn authorize_guess(
+        &mut self,
+        signer: Pubkey,
+        wager: u64,
+        timestamp: i64,
+    ) -> anchor_lang::Result<()> {
+        if signer == self.authority {
+            return Ok(());
+        }
+
+        if self.session_key == Pubkey::default() || signer != self.session_key {
+            return Err(error!(SimpleRandomnessError::InvalidAuthority));
+        }
+        if timestamp >= self.session_expiration {
+            return Err(error!(SimpleRandomnessError::SessionExpired));
+        }
+        if wager > self.session_allowance {
+            return Err(error!(SimpleRandomnessError::SessionAllowanceExceeded));
+        }
+
+        self.session_allowance -= wager;
+
+        Ok(())
+    }
+}
+
+/// The UserState layout before any fields were added to it, kept so `migrate_user` can read
+/// user accounts created with it. This is not an account type, it only describes the bytes after
+/// the UserState discriminator, and is declared exactly as UserState was to keep the same layout.
+#[zero_copy(unsafe)]
+pub struct BaselineUserState {
+    pub bump: u8,
+    pub authority: Pubkey,
+    pub switchboard_request: Pubkey,
+    pub guess: u32,
+    pub result: u32,
+    pub request_timestamp: i64,
+    pub settled_timestamp: i64,
+}
+
+// Plain zero copy structs don't derive these, and the packed layout has no padding.
+unsafe impl anchor_lang::__private::bytemuck::Pod for BaselineUserState {}
+unsafe impl anchor_lang::__private::bytemuck::Zeroable for BaselineUserState {}
+
+/// Represents a single settled round in a users guess history.
+#[zero_copy(unsafe)]
+pub struct GuessRecord {
+    /// The users guess.
+    pub guess: u32,
+    /// The Switchboard Function result.
+    pub result: u32,
+    /// Whether the guess matched the result.
+    pub won: bool,
+    /// The slot the round was settled in.
+    pub settled_slot: u64,
+}
+
+/// Represents the program-wide statistics updated by every settle.
+/// Used to audit the observed return-to-player and distribution of Switchboard Function results.
+#[account(zero_copy(unsafe))]
+pub struct GlobalStats {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// The number of settled rounds.
+    pub total_rounds: u64,
+    /// The number of settled rounds where the guess matched the result.
+    pub total_wins: u64,
+    /// The sum of all wagers across settled rounds, in lamports.
+    pub total_wagered: u64,
+    /// The sum of all payouts to winners, in lamports.
+    pub total_paid_out: u64,
+    /// The number of times each result was settled for guesses made with the canonical
+    /// `[MIN_RESULT, MAX_RESULT]` range, indexed by `result - MIN_RESULT`. Guesses with any other
+    /// range draw from a different distribution, so they're only counted in the totals.
+    pub result_histogram: [u64; RESULT_HISTOGRAM_LEN],
+}
+
+impl GlobalStats {
+    /// Records a settled round. Only rounds guessed with the canonical `[MIN_RESULT, MAX_RESULT]`
+    /// range are counted in the histogram.
+    pub fn record(
+        &mut self,
+        result: u32,
+        min_result: u32,
+        max_result: u32,
+        wager: u64,
+        payout: u64,
+        won: bool,
+    ) {
+        self.total_rounds = self.total_rounds.saturating_add(1);
+        if won {
+            self.total_wins = self.total_wins.saturating_add(1);
+        }
+        self.total_wagered = self.total_wagered.saturating_add(wager);
+        self.total_paid_out = self.total_paid_out.saturating_add(payout);
+
+        if (min_result, max_result) == (MIN_RESULT, MAX_RESULT) {
+            let bucket = (result - MIN_RESULT) as usize;
+            self.result_histogram[bucket] = self.result_histogram[bucket].saturating_add(1);
+        }
+    }
+}
+
+/// Represents the treasury which collects the protocol fee.
+/// The treasury's lamports, less rent, can be withdrawn by the program authority.
+#[account(zero_copy(unsafe))]
+pub struct Treasury {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// The sum of all protocol fees collected, in lamports.
+    pub total_collected: u64,
+}
+
+/// Represents the house vault which bankrolls the game.
+/// The vault's lamports, less rent, are owned pro-rata by the holders of the share mint.
+#[account(zero_copy(unsafe))]
+pub struct HouseVault {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// The SPL mint issued to liquidity providers, with the house vault as mint authority.
+    pub share_mint: Pubkey,
+    /// The cut of each payout retained by the house, in basis points.
+    pub house_edge_bps: u16,
+    /// The sum of the payouts owed if every unsettled guess wins.
+    pub outstanding_liability: u64,
+    /// The share of the house vault, in basis points, that `outstanding_liability` may reach.
+    pub max_exposure_bps: u16,
+}
+
+impl HouseVault {
+    /// Returns the amount of lamports the house holds without dropping below rent exemption.
+    pub fn available_lamports(account: &AccountInfo) -> anchor_lang::Result<u64> {
+        let rent_exempt_lamports = Rent::get()?.minimum_balance(account.data_len());
+        Ok(account.lamports().saturating_sub(rent_exempt_lamports))
+    }
+
+    /// Returns the amount paid out to a winning guess for the given wager and result range, net of
+    /// the house edge.
+    pub fn payout(&self, wager: u64, min_result: u32, max_result: u32) -> anchor_lang::Result<u64> {
+        let gross = (wager as u128)
+            .checked_mul(u128::from(max_result.saturating_sub(min_result)) + 1)
+            .ok_or(error!(SimpleRandomnessError::MathOverflow))?;
+        let edge = gross * u128::from(self.house_edge_bps) / u128::from(MAX_HOUSE_EDGE_BPS);
+        u64::try_from(gross - edge).map_err(|_| error!(SimpleRandomnessError::MathOverflow))
+    }
+
+    /// Returns the maximum open liability allowed for the given vault balance.
+    pub fn max_exposure(&self, house_lamports: u64) -> u64 {
+        (u128::from(house_lamports) * u128::from(self.max_exposure_bps)
+            / u128::from(MAX_EXPOSURE_BPS)) as u64
+    }
+
+    /// Adds the max payout of a new wager to the open liability, as long as it stays within the
+    /// exposure limit for the given vault balance.
+    pub fn reserve(
+        &mut self,
+        house_lamports: u64,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<()> {
+        let outstanding_liability = self
+            .outstanding_liability
+            .checked_add(self.payout(wager, min_result, max_result)?)
+            .ok_or(error!(SimpleRandomnessError::MathOverflow))?;
+        if outstanding_liability > self.max_exposure(house_lamports) {
+            return Err(error!(SimpleRandomnessError::ExposureLimitExceeded));
+        }
+        self.outstanding_liability = outstanding_liability;
+
+        Ok(())
+    }
+
+    /// Removes the max payout of a wager from the open liability and returns it.
+    pub fn release(
+        &mut self,
+        wager: u64,
+        min_result: u32,
+        max_result: u32,
+    ) -> anchor_lang::Result<u64> {
+        let max_payout = self.payout(wager, min_result, max_result)?;
+        self.outstanding_liability = self.outstanding_liability.saturating_sub(max_payout);
+
+        Ok(max_payout)
+    }
+
+    /// Transfers lamports out of the house vault.
+    pub fn pay_out(
+        house: &AccountInfo,
+        destination: &AccountInfo,
+        amount: u64,
+    ) -> anchor_lang::Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+        if HouseVault::available_lamports(house)? < amount {
+            return Err(error!(SimpleRandomnessError::InsufficientHouseLiquidity));
+        }
+
+        **house.try_borrow_mut_lamports()? -= amount;
+        **destination.try_borrow_mut_lamports()? += amount;
+
+        Ok(())
+    }
+
+    /// Returns the number of shares minted for a deposit, given the vault's net asset value and
+    /// the current share supply. Deposits are rejected while outstanding shares are backed by
+    /// nothing, since they would otherwise be diluted for free.
+    pub fn shares_for_deposit(amount: u64, nav: u64, supply: u64) -> anchor_lang::Result<u64> {
+        if supply > 0 && nav == 0 {
+            return Err(error!(SimpleRandomnessError::HouseVaultDepleted));
+        }
+
+        let shares = u128::from(amount) * (u128::from(supply) + u128::from(VIRTUAL_LIQUIDITY))
+            / (u128::from(nav) + u128::from(VIRTUAL_LIQUIDITY));
+        u64::try_from(shares).map_err(|_| error!(SimpleRandomnessError::MathOverflow))
+    }
+
+    /// Returns the amount of lamports redeemed for the given shares, given the vault's net asset
+    /// value and the current share supply. Never exceeds the net asset value.
+    pub fn amount_for_shares(shares: u64, nav: u64, supply: u64) -> anchor_lang::Result<u64> {
+        if shares > supply {
+            return Err(error!(SimpleRandomnessError::InvalidAmount));
+        }
+
+        let amount = u128::from(shares) * (u128::from(nav) + u128::from(VIRTUAL_LIQUIDITY))
+            / (u128::from(supply) + u128::from(VIRTUAL_LIQUIDITY));
+        Ok(u64::try_from(amount).unwrap_or(u64::MAX).min(nav))
+    }
+}
+
+#[derive(Accounts)]
 pub struct Initialize<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
@@ -269,8 +1332,18 @@ pub struct Initialize<'info> {
         bump
     )]
     pub program_state: AccountLoader<'info, ProgramState>,
-    /// CHECK: an account authorized to change the program config.
-    pub authority: AccountInfo<'info>,
+    /// The program's upgrade authority, which becomes the authority of the program config.
+    pub authority: Signer<'info>,
+
+    // UPGRADE AUTHORITY ACCOUNTS
+    // Only the upgrade authority can initialize the program so the deploy can't be front-run.
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::SwitchboardRandomnessCallback>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ SimpleRandomnessError::InvalidAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
 
     // SWITCHBOARD ACCOUNTS
     // Ensure our authority owns this function
@@ -289,6 +1362,79 @@ pub struct Initialize<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetFunction<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    // SWITCHBOARD ACCOUNTS
+    #[account(
+        constraint =
+            // Ensure custom requests are allowed
+            switchboard_function.load()?.requests_disabled == 0
+    )]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = pending_authority @ SimpleRandomnessError::InvalidAuthority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub pending_authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct CreateUser<'info> {
     #[account(mut)]
@@ -313,6 +1459,13 @@ pub struct CreateUser<'info> {
     /// CHECK: the user's authority must sign to create a new user
     pub authority: AccountInfo<'info>,
 
+    // Passed to the Switchboard Function in the request params.
+    #[account(
+        seeds = [PROGRAM_SEED, STATS_SEED],
+        bump = stats.load()?.bump,
+    )]
+    pub stats: AccountLoader<'info, GlobalStats>,
+
     // SWITCHBOARD ACCOUNTS
     /// CHECK: program ID checked.
     #[account(executable, address = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
@@ -355,12 +1508,31 @@ pub struct CreateUser<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetSession<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [USER_SEED, authority.key().as_ref()],
+        bump = user.load()?.bump,
+        has_one = authority,
+    )]
+    pub user: AccountLoader<'info, UserState>,
+    pub authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct Guess<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
 
     // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+
     #[account(
         mut,
         seeds = [USER_SEED, authority.key().as_ref()],
@@ -370,8 +1542,24 @@ pub struct Guess<'info> {
     )]
     pub user: AccountLoader<'info, UserState>,
 
-    /// CHECK: the user's authority must sign to guess on their behalf
-    pub authority: Signer<'info>,
+    /// CHECK: validated by the user's has_one constraint
+    pub authority: AccountInfo<'info>,
+    /// The user's authority or its session key.
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.load()?.bump,
+    )]
+    pub house: AccountLoader<'info, HouseVault>,
+
+    // Passed to the Switchboard Function in the request params.
+    #[account(
+        seeds = [PROGRAM_SEED, STATS_SEED],
+        bump = stats.load()?.bump,
+    )]
+    pub stats: AccountLoader<'info, GlobalStats>,
 
     // SWITCHBOARD ACCOUNTS
     /// CHECK:
@@ -386,9 +1574,39 @@ pub struct Guess<'info> {
     /// CHECK: validated by Switchboard CPI
     #[account(mut)]
     pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
-    /// CHECK: validated by Switchboard CPI
+    #[account(
+        mut,
+        address = switchboard_request.escrow,
+    )]
+    pub switchboard_request_escrow: Box<Account<'info, TokenAccount>>,
+
+    // TOKEN ACCOUNTS
+    pub token_program: Program<'info, Token>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundRequestEscrow<'info> {
     #[account(mut)]
-    pub switchboard_request_escrow: AccountInfo<'info>,
+    pub payer: Signer<'info>,
+
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        seeds = [USER_SEED, user.load()?.authority.as_ref()],
+        bump = user.load()?.bump,
+        has_one = switchboard_request,
+    )]
+    pub user: AccountLoader<'info, UserState>,
+
+    // SWITCHBOARD ACCOUNTS
+    pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
+    #[account(
+        mut,
+        address = switchboard_request.escrow,
+    )]
+    pub switchboard_request_escrow: Box<Account<'info, TokenAccount>>,
 
     // TOKEN ACCOUNTS
     pub token_program: Program<'info, Token>,
@@ -402,6 +1620,8 @@ pub struct Settle<'info> {
     // RANDOMNESS PROGRAM ACCOUNTS
     #[account(
         mut,
+        seeds = [USER_SEED, user.load()?.authority.as_ref()],
+        bump = user.load()?.bump,
         has_one = switchboard_request,
     )]
     pub user: AccountLoader<'info, UserState>,
@@ -416,6 +1636,8 @@ pub struct Settle<'info> {
     //     )?
     // )]
     #[account(
+        constraint = switchboard_request.function == switchboard_function.key()
+            @ SimpleRandomnessError::InvalidSwitchboardFunction,
         constraint = switchboard_function.load()?.validate_request(
             &switchboard_request,
             &enclave_signer.to_account_info()
@@ -423,16 +1645,87 @@ pub struct Settle<'info> {
     )]
     pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
     pub enclave_signer: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.load()?.bump,
+    )]
+    pub house: AccountLoader<'info, HouseVault>,
+    /// CHECK: the user's authority receives any winnings.
+    #[account(mut, address = user.load()?.authority)]
+    pub authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED, STATS_SEED],
+        bump = stats.load()?.bump,
+    )]
+    pub stats: AccountLoader<'info, GlobalStats>,
+
+    // PROGRAM ACCOUNTS
+    // Only the Switchboard Function registered in the program config can settle guesses.
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = switchboard_function @ SimpleRandomnessError::InvalidSwitchboardFunction,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED, TREASURY_SEED],
+        bump = treasury.load()?.bump,
+    )]
+    pub treasury: AccountLoader<'info, Treasury>,
+}
+
+#[derive(Accounts)]
+pub struct CancelGuess<'info> {
+    // RANDOMNESS PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [USER_SEED, authority.key().as_ref()],
+        bump = user.load()?.bump,
+        has_one = switchboard_request,
+        has_one = authority,
+    )]
+    pub user: AccountLoader<'info, UserState>,
+
+    /// CHECK: the user's authority must sign to cancel their guess and receives the refund
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.load()?.bump,
+    )]
+    pub house: AccountLoader<'info, HouseVault>,
+
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+
+    // SWITCHBOARD ACCOUNTS
+    pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
 }
 
 #[derive(Accounts)]
 pub struct Close<'info> {
     // RANDOMNESS PROGRAM ACCOUNTS
+    // A pending guess' wager and liability are only cleared by settle or cancel_guess.
     #[account(
         mut,
         close = authority,
         has_one = switchboard_request,
         has_one = authority,
+        constraint = user.load()?.request_timestamp == 0 || user.load()?.settled_timestamp > 0
+            @ SimpleRandomnessError::GuessPending,
     )]
     pub user: AccountLoader<'info, UserState>,
 
@@ -463,6 +1756,215 @@ pub struct Close<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct MigrateUser<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // RANDOMNESS PROGRAM ACCOUNTS
+    // Loaded as an AccountInfo since accounts created before the migration are too small to load.
+    /// CHECK: the discriminator is checked in the instruction.
+    #[account(
+        mut,
+        owner = crate::id(),
+        seeds = [USER_SEED, authority.key().as_ref()],
+        bump,
+    )]
+    pub user: AccountInfo<'info>,
+    /// CHECK: only used to derive the user PDA.
+    pub authority: AccountInfo<'info>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeStats<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<GlobalStats>(),
+        payer = payer,
+        seeds = [PROGRAM_SEED, STATS_SEED],
+        bump
+    )]
+    pub stats: AccountLoader<'info, GlobalStats>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeHouse<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<HouseVault>(),
+        payer = payer,
+        seeds = [HOUSE_SEED],
+        bump
+    )]
+    pub house: AccountLoader<'info, HouseVault>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [HOUSE_SHARE_MINT_SEED],
+        bump,
+        mint::decimals = HOUSE_SHARE_DECIMALS,
+        mint::authority = house,
+    )]
+    pub share_mint: Box<Account<'info, Mint>>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxExposure<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.load()?.bump,
+    )]
+    pub house: AccountLoader<'info, HouseVault>,
+}
+
+#[derive(Accounts)]
+pub struct DepositLiquidity<'info> {
+    #[account(mut)]
+    pub provider: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.load()?.bump,
+        has_one = share_mint,
+    )]
+    pub house: AccountLoader<'info, HouseVault>,
+    #[account(mut)]
+    pub share_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = share_mint,
+    )]
+    pub provider_shares: Box<Account<'info, TokenAccount>>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawLiquidity<'info> {
+    #[account(mut)]
+    pub provider: Signer<'info>,
+
+    // HOUSE ACCOUNTS
+    #[account(
+        mut,
+        seeds = [HOUSE_SEED],
+        bump = house.load()?.bump,
+        has_one = share_mint,
+    )]
+    pub house: AccountLoader<'info, HouseVault>,
+    #[account(mut)]
+    pub share_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint = share_mint,
+        token::authority = provider,
+    )]
+    pub provider_shares: Box<Account<'info, TokenAccount>>,
+
+    // SYSTEM ACCOUNTS
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<Treasury>(),
+        payer = payer,
+        seeds = [PROGRAM_SEED, TREASURY_SEED],
+        bump
+    )]
+    pub treasury: AccountLoader<'info, Treasury>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED, TREASURY_SEED],
+        bump = treasury.load()?.bump,
+    )]
+    pub treasury: AccountLoader<'info, Treasury>,
+
+    /// CHECK: receives the withdrawn fees
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+}
+
 #[error_code]
 #[derive(Eq, PartialEq)]
 pub enum SimpleRandomnessError {
@@ -472,6 +1974,203 @@ pub enum SimpleRandomnessError {
     RequestNotReady,
     #[msg("Request already settled")]
     RequestAlreadySettled,
+    #[msg("Request has not expired yet")]
+    RequestNotExpired,
+    #[msg("Switchboard Function does not match the program config")]
+    InvalidSwitchboardFunction,
+    #[msg("Program is paused")]
+    ProgramPaused,
+    #[msg("Request timeout is below the minimum or configured Switchboard request expiration")]
+    InvalidRequestTimeout,
+    #[msg("Request expiration is below the minimum Switchboard request expiration")]
+    InvalidRequestExpiration,
+    #[msg("Switchboard request escrow can't cover the request")]
+    InsufficientRequestEscrow,
+    #[msg("Protocol fee exceeds the maximum")]
+    InvalidFee,
+    #[msg("Treasury can't cover the withdrawal")]
+    InsufficientFees,
+    #[msg("Session duration must be positive and within the maximum")]
+    InvalidSessionDuration,
+    #[msg("Session key has expired")]
+    SessionExpired,
+    #[msg("Wager exceeds the session key's remaining allowance")]
+    SessionAllowanceExceeded,
     #[msg("Random result is out-of-bounds")]
     RandomResultOutOfBounds,
+    #[msg("House edge must be between 0 and 10000 basis points")]
+    InvalidHouseEdge,
+    #[msg("House vault has insufficient liquidity to cover unsettled guesses")]
+    InsufficientHouseLiquidity,
+    #[msg("House vault has no liquidity left to back its outstanding shares")]
+    HouseVaultDepleted,
+    #[msg("Invalid amount")]
+    InvalidAmount,
+    #[msg("Max exposure must be between 0 and 10000 basis points")]
+    InvalidExposureLimit,
+    #[msg("Guess would push the house's open liability above its exposure limit")]
+    ExposureLimitExceeded,
+    #[msg("Result range is invalid or outside the program bounds")]
+    InvalidResultRange,
+    #[msg("Guess is outside the result range")]
+    InvalidGuess,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Guess is pending, settle or cancel it first")]
+    GuessPending,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_history_wraps_around() {
+        let mut user: UserState = anchor_lang::__private::bytemuck::Zeroable::zeroed();
+        for i in 0..(USER_HISTORY_LEN as u32 + 3) {
+            user.push_history(GuessRecord {
+                guess: i,
+                result: i,
+                won: true,
+                settled_slot: u64::from(i),
+            });
+        }
+
+        let (history_head, history_len) = (user.history_head, user.history_len);
+        assert_eq!(history_head, 3);
+        assert_eq!(history_len, USER_HISTORY_LEN as u32);
+        // The oldest rounds were overwritten by the most recent ones
+        let (newest, oldest) = (user.history[2].settled_slot, user.history[3].settled_slot);
+        assert_eq!(newest, USER_HISTORY_LEN as u64 + 2);
+        assert_eq!(oldest, 3);
+    }
+
+    #[test]
+    fn test_migrate_baseline_user() {
+        let mut baseline: BaselineUserState = anchor_lang::__private::bytemuck::Zeroable::zeroed();
+        baseline.bump = 254;
+        baseline.authority = Pubkey::new_unique();
+        baseline.switchboard_request = Pubkey::new_unique();
+        baseline.guess = 7;
+        baseline.result = 3;
+        baseline.request_timestamp = 1_000;
+        baseline.settled_timestamp = 2_000;
+
+        let mut data = UserState::discriminator().to_vec();
+        data.extend_from_slice(anchor_lang::__private::bytemuck::bytes_of(&baseline));
+        assert_eq!(data.len(), BASELINE_USER_STATE_LEN);
+        data.resize(8 + std::mem::size_of::<UserState>(), 0);
+        UserState::migrate_baseline(&mut data);
+
+        let user: &UserState = anchor_lang::__private::bytemuck::from_bytes(&data[8..]);
+        let (bump, guess, result) = (user.bump, user.guess, user.result);
+        let (request_timestamp, settled_timestamp) =
+            (user.request_timestamp, user.settled_timestamp);
+        let (wager, min_result, max_result) = (user.wager, user.min_result, user.max_result);
+        let history_len = user.history_len;
+        assert_eq!(bump, 254);
+        assert_eq!(user.authority, baseline.authority);
+        assert_eq!(user.switchboard_request, baseline.switchboard_request);
+        assert_eq!(guess, 7);
+        assert_eq!(result, 3);
+        assert_eq!(request_timestamp, 1_000);
+        assert_eq!(settled_timestamp, 2_000);
+        assert_eq!(wager, 0);
+        assert_eq!(min_result, MIN_RESULT);
+        assert_eq!(max_result, MAX_RESULT);
+        assert_eq!(history_len, 0);
+    }
+
+    #[test]
+    fn test_stats_record() {
+        let mut stats: GlobalStats = anchor_lang::__private::bytemuck::Zeroable::zeroed();
+        stats.record(MIN_RESULT, MIN_RESULT, MAX_RESULT, 100, 0, false);
+        stats.record(MAX_RESULT, MIN_RESULT, MAX_RESULT, 100, 900, true);
+        // Guesses with any other range are left out of the histogram
+        stats.record(MIN_RESULT, MIN_RESULT, MAX_RESULT - 1, 100, 0, false);
+
+        let (total_rounds, total_wins) = (stats.total_rounds, stats.total_wins);
+        let (total_wagered, total_paid_out) = (stats.total_wagered, stats.total_paid_out);
+        let result_histogram = stats.result_histogram;
+        assert_eq!(total_rounds, 3);
+        assert_eq!(total_wins, 1);
+        assert_eq!(total_wagered, 300);
+        assert_eq!(total_paid_out, 900);
+        assert_eq!(result_histogram[0], 1);
+        assert_eq!(result_histogram[RESULT_HISTOGRAM_LEN - 1], 1);
+        assert_eq!(result_histogram.iter().sum::<u64>(), 2);
+    }
+
+    #[test]
+    fn test_house_share_pricing() {
+        // The first deposit mints shares 1:1
+        assert_eq!(HouseVault::shares_for_deposit(1_000, 0, 0).unwrap(), 1_000);
+        assert_eq!(
+            HouseVault::amount_for_shares(1_000, 1_000, 1_000).unwrap(),
+            1_000
+        );
+
+        // A donation to a near-empty vault can't round the next deposit down to zero shares
+        let shares = HouseVault::shares_for_deposit(1_000_000, 1_000_000_001, 1).unwrap();
+        assert!(shares > 0);
+
+        // Redemptions never exceed the vault's net asset value, even after losses
+        assert_eq!(
+            HouseVault::amount_for_shares(1_000, 500, 1_000).unwrap(),
+            500
+        );
+
+        // Deposits into a depleted vault would be diluted by the worthless outstanding shares
+        assert_eq!(
+            HouseVault::shares_for_deposit(1_000, 0, 1_000).unwrap_err(),
+            error!(SimpleRandomnessError::HouseVaultDepleted)
+        );
+    }
+
+    #[test]
+    fn test_program_state_fee() {
+        let mut program_state: ProgramState = anchor_lang::__private::bytemuck::Zeroable::zeroed();
+        assert_eq!(program_state.fee(1_000_000).unwrap(), 0);
+
+        program_state.fee_bps = 250;
+        assert_eq!(program_state.fee(1_000_000).unwrap(), 25_000);
+        assert_eq!(program_state.fee(39).unwrap(), 0);
+
+        program_state.fee_bps = MAX_FEE_BPS;
+        assert_eq!(program_state.fee(u64::MAX).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_authorize_guess_with_session_key() {
+        let mut user: UserState = anchor_lang::__private::bytemuck::Zeroable::zeroed();
+        user.authority = Pubkey::new_unique();
+        let session_key = Pubkey::new_unique();
+
+        // The authority can always guess, a session key can't until one is registered
+        assert!(user.authorize_guess(user.authority, u64::MAX, 0).is_ok());
+        assert_eq!(
+            user.authorize_guess(session_key, 1, 0).unwrap_err(),
+            error!(SimpleRandomnessError::InvalidAuthority)
+        );
+
+        user.session_key = session_key;
+        user.session_expiration = 100;
+        user.session_allowance = 150;
+        assert!(user.authorize_guess(session_key, 100, 50).is_ok());
+        let session_allowance = user.session_allowance;
+        assert_eq!(session_allowance, 50);
+        assert_eq!(
+            user.authorize_guess(session_key, 51, 50).unwrap_err(),
+            error!(SimpleRandomnessError::SessionAllowanceExceeded)
+        );
+        assert_eq!(
+            user.authorize_guess(session_key, 1, 100).unwrap_err(),
+            error!(SimpleRandomnessError::SessionExpired)
+        );
+        assert_eq!(
+            user.authorize_guess(Pubkey::new_unique(), 1, 50)
+                .unwrap_err(),
+            error!(SimpleRandomnessError::InvalidAuthority)
+        );
+    }
 }
@@ -3,22 +3,60 @@
 // This Solana program will allow you to initialize a lottery game with a Switchboard Function.
 // Users can then buy tickets and enter into a drawing. When the lottery is initialized, a
 // Switchboard request will be created and scheduled to execute when the lottery concludes.
+// The Switchboard Function submits raw randomness to draw_winner, which derives the winning ticket
+// on-chain so the drawing can be verified from chain data alone.
+// Each authority creates a LotteryHost with initialize_lottery_host, which numbers its lotteries so
+// it can run many of them in parallel and one after another.
+// A recurring lottery is rescheduled by draw_winner after each payout, re-triggering the same
+// Switchboard request for the next round so it runs without any further transactions. The next
+// round's oracle bounty is topped up from the jackpot before the protocol fee and payout are taken.
+// The program authority can point the program at a new Switchboard Function with set_function and
+// hand off its authority in two steps with propose_authority and accept_authority.
+// In an emergency the authority can pause create_lottery and buy_ticket, while draw_winner keeps
+// working so running lotteries can conclude. The default lottery duration, oracle bounty,
+// request expiration, and protocol fee can be tuned with update_config.
+// A protocol fee is taken from each jackpot in draw_winner and collected by the treasury, which is
+// created with initialize_treasury and emptied by the authority with withdraw_fees.
+// If a lottery is never drawn, ticket holders can reclaim their entry fee with claim_refund once
+// the configured refund delay, which must outlast the request expiration, has passed since the
+// lottery closed. The first refund closes the Switchboard request so the lottery can't be drawn,
+// and returns the leftover lamports to the lottery authority.
 
-use anchor_spl::token::{CloseAccount, Token, TokenAccount};
+use anchor_spl::token::{CloseAccount, Token, TokenAccount, Transfer};
 use switchboard_solana::prelude::*;
 
 declare_id!("6AKXZiKbmj3D45bDZpa9fo6vUV4qGeeeRCZ5qRhE4Ve4");
 
 pub const PROGRAM_SEED: &[u8] = b"SIMPLE_LOTTERY";
 pub const LOTTERY_SEED: &[u8] = b"LOTTERY_STATE";
+pub const LOTTERY_HOST_SEED: &[u8] = b"LOTTERY_HOST";
+pub const LOTTERY_VAULT_SEED: &[u8] = b"LOTTERY_VAULT";
+pub const TREASURY_SEED: &[u8] = b"TREASURY";
+
+/// Pauses the create_lottery instruction when set in `ProgramState::paused_ixs`.
+pub const PAUSE_CREATE_LOTTERY: u8 = 1 << 0;
+/// Pauses the buy_ticket instruction when set in `ProgramState::paused_ixs`.
+pub const PAUSE_BUY_TICKET: u8 = 1 << 1;
 
 /// The maximum number of tickets allowed to enter a lottery.
 /// This could be dynamic but for this example its hard coded.
 pub const MAX_TICKETS: usize = 256;
 
-/// The default number of slots per lottery.
+/// The default number of slots per lottery, used until changed with update_config.
 pub const DEFAULT_LOTTERY_DURATION_SLOTS: u32 = 9000; // ~1 hour at 400 ms/slot
 
+/// The default number of slots a Switchboard request can be processed in, ~ 15 min at 400 ms/slot.
+pub const DEFAULT_SLOTS_UNTIL_EXPIRATION: u64 = 2250;
+
+/// The minimum number of slots a Switchboard request can be processed in, ~ 1 min at 400 ms/slot.
+pub const MIN_SLOTS_UNTIL_EXPIRATION: u64 = 150;
+
+/// The maximum protocol fee, in basis points of each jackpot, that can be configured.
+pub const MAX_FEE_BPS: u16 = 10_000;
+
+/// The default number of slots after a lottery closes before undrawn tickets can be refunded.
+pub const DEFAULT_REFUND_DELAY_SLOTS: u64 = 9000; // ~1 hour at 400 ms/slot
+
 #[program]
 pub mod scheduled_lottery_request {
     use switchboard_solana::wrap_native;
@@ -32,6 +70,121 @@ pub mod scheduled_lottery_request {
         program_state.bump = ctx.bumps.program_state;
         program_state.authority = *ctx.accounts.authority.key;
         program_state.switchboard_function = ctx.accounts.switchboard_function.key();
+        program_state.lottery_duration_slots = DEFAULT_LOTTERY_DURATION_SLOTS;
+        program_state.slots_until_expiration = DEFAULT_SLOTS_UNTIL_EXPIRATION;
+        program_state.refund_delay_slots = DEFAULT_REFUND_DELAY_SLOTS;
+
+        Ok(())
+    }
+
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        lottery_duration_slots: Option<u32>,
+        default_bounty: Option<u64>,
+        slots_until_expiration: Option<u64>,
+        fee_bps: Option<u16>,
+        refund_delay_slots: Option<u64>,
+    ) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+
+        if let Some(lottery_duration_slots) = lottery_duration_slots {
+            if lottery_duration_slots == 0 {
+                return Err(error!(LotteryError::InvalidLotteryDuration));
+            }
+            program_state.lottery_duration_slots = lottery_duration_slots;
+        }
+
+        if let Some(default_bounty) = default_bounty {
+            program_state.default_bounty = default_bounty;
+        }
+
+        if let Some(slots_until_expiration) = slots_until_expiration {
+            if slots_until_expiration < MIN_SLOTS_UNTIL_EXPIRATION {
+                return Err(error!(LotteryError::InvalidRequestExpiration));
+            }
+            program_state.slots_until_expiration = slots_until_expiration;
+        }
+
+        if let Some(fee_bps) = fee_bps {
+            if fee_bps > MAX_FEE_BPS {
+                return Err(error!(LotteryError::InvalidFee));
+            }
+            program_state.fee_bps = fee_bps;
+        }
+
+        if let Some(refund_delay_slots) = refund_delay_slots {
+            program_state.refund_delay_slots = refund_delay_slots;
+        }
+
+        // Refunds must only open once the Switchboard request can no longer be fulfilled, so a
+        // lottery can't be both drawn and refunded.
+        if program_state.refund_delay_slots <= program_state.slots_until_expiration {
+            return Err(error!(LotteryError::InvalidRefundDelay));
+        }
+
+        Ok(())
+    }
+
+    pub fn set_function(ctx: Context<SetFunction>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        let old_function = program_state.switchboard_function;
+        program_state.switchboard_function = ctx.accounts.switchboard_function.key();
+
+        emit!(SwitchboardFunctionUpdated {
+            old_function,
+            new_function: program_state.switchboard_function,
+        });
+
+        Ok(())
+    }
+
+    pub fn propose_authority(
+        ctx: Context<ProposeAuthority>,
+        pending_authority: Pubkey,
+    ) -> anchor_lang::Result<()> {
+        ctx.accounts.program_state.load_mut()?.pending_authority = pending_authority;
+
+        emit!(AuthorityProposed {
+            authority: ctx.accounts.authority.key(),
+            pending_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        let old_authority = program_state.authority;
+        program_state.authority = ctx.accounts.pending_authority.key();
+        program_state.pending_authority = Pubkey::default();
+
+        emit!(AuthorityAccepted {
+            old_authority,
+            new_authority: program_state.authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn pause(ctx: Context<SetPaused>, paused_ixs: Option<u8>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        match paused_ixs {
+            Some(paused_ixs) => program_state.paused_ixs |= paused_ixs,
+            None => program_state.paused = true,
+        }
+
+        Ok(())
+    }
+
+    pub fn unpause(ctx: Context<SetPaused>, paused_ixs: Option<u8>) -> anchor_lang::Result<()> {
+        let mut program_state = ctx.accounts.program_state.load_mut()?;
+        match paused_ixs {
+            Some(paused_ixs) => program_state.paused_ixs &= !paused_ixs,
+            None => {
+                program_state.paused = false;
+                program_state.paused_ixs = 0;
+            }
+        }
 
         Ok(())
     }
@@ -40,13 +193,30 @@ pub mod scheduled_lottery_request {
         ctx: Context<CreateLottery>,
         entry_fee: u64,
         duration_slots: Option<u32>,
+        recurring: bool,
     ) -> anchor_lang::Result<()> {
+        let program_state = ctx.accounts.program_state.load()?;
+        program_state.assert_not_paused(PAUSE_CREATE_LOTTERY)?;
+        let default_duration_slots = program_state.lottery_duration_slots;
+        let (bounty, slots_until_expiration) = (
+            program_state.default_bounty,
+            program_state.slots_until_expiration,
+        );
+        drop(program_state);
+
+        let lottery_id = ctx.accounts.lottery_host.load()?.next_lottery_id;
+
         // Parameters used by the Switchboard Function to determine the lottery winner.
-        let request_params = format!("PID={},LOTTERY={}", crate::id(), ctx.accounts.lottery.key(),);
+        let request_params = format!(
+            "PID={},LOTTERY={},LOTTERY_ID={}",
+            crate::id(),
+            ctx.accounts.lottery.key(),
+            lottery_id,
+        );
         let container_params = request_params.into_bytes();
 
-        let lottery_settlement_slot = Clock::get()?.slot
-            + u64::from(duration_slots.unwrap_or(DEFAULT_LOTTERY_DURATION_SLOTS));
+        let duration_slots = duration_slots.unwrap_or(default_duration_slots);
+        let lottery_settlement_slot = Clock::get()?.slot + u64::from(duration_slots);
 
         // Create the Switchboard request account.
         let request_init_ctx = FunctionRequestInit {
@@ -92,9 +262,9 @@ pub mod scheduled_lottery_request {
         trigger_ctx.invoke_signed(
             ctx.accounts.switchboard.clone(),
             // bounty - the amount of SOL to pay the Switchboard Function for executing the request
-            None,
+            Some(bounty),
             // slots_until_expiration - the number of slots until the request expires
-            None,
+            Some(slots_until_expiration),
             // valid_after_slot - the slot when the request can be executed
             // !! IMPORTANT !! - we're scheduling our request to execute as soon as the lottery ends
             Some(lottery_settlement_slot),
@@ -102,6 +272,7 @@ pub mod scheduled_lottery_request {
             &[&[
                 LOTTERY_SEED,
                 ctx.accounts.authority.key().as_ref(),
+                &lottery_id.to_le_bytes(),
                 // &[*ctx.bumps.get("lottery").unwrap()],
                 &[ctx.bumps.lottery],
             ]],
@@ -117,11 +288,40 @@ pub mod scheduled_lottery_request {
 
         lottery.open_slot = Clock::get()?.slot;
         lottery.close_slot = lottery_settlement_slot;
+        lottery.lottery_id = lottery_id;
+        lottery.recurring = recurring;
+        lottery.duration_slots = duration_slots;
+
+        let mut lottery_host = ctx.accounts.lottery_host.load_mut()?;
+        lottery_host.next_lottery_id = lottery_id
+            .checked_add(1)
+            .ok_or(error!(LotteryError::MathOverflow))?;
+
+        emit!(LotteryCreated {
+            lottery: ctx.accounts.lottery.key(),
+            authority: ctx.accounts.authority.key(),
+            lottery_id,
+            entry_fee,
+            close_slot: lottery_settlement_slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_lottery_host(ctx: Context<InitializeLotteryHost>) -> anchor_lang::Result<()> {
+        let mut lottery_host = ctx.accounts.lottery_host.load_init()?;
+        lottery_host.bump = ctx.bumps.lottery_host;
+        lottery_host.authority = ctx.accounts.authority.key();
 
         Ok(())
     }
 
     pub fn buy_ticket(ctx: Context<BuyTicket>) -> anchor_lang::Result<()> {
+        ctx.accounts
+            .program_state
+            .load()?
+            .assert_not_paused(PAUSE_BUY_TICKET)?;
+
         if ctx.accounts.lottery.load()?.num_tickets >= MAX_TICKETS as u32 {
             return Err(error!(LotteryError::LotterySoldOut));
         }
@@ -138,6 +338,7 @@ pub mod scheduled_lottery_request {
             &[&[
                 LOTTERY_SEED,
                 ctx.accounts.lottery.load()?.authority.key().as_ref(),
+                &ctx.accounts.lottery.load()?.lottery_id.to_le_bytes(),
                 &[ctx.accounts.lottery.load()?.bump],
             ]],
             ctx.accounts.lottery.load()?.entry_fee,
@@ -158,7 +359,7 @@ pub mod scheduled_lottery_request {
         Ok(())
     }
 
-    pub fn draw_winner(ctx: Context<DrawWinner>, winner: Pubkey) -> anchor_lang::Result<()> {
+    pub fn draw_winner(ctx: Context<DrawWinner>, randomness: [u8; 32]) -> anchor_lang::Result<()> {
         if ctx.accounts.lottery.load()?.has_ended {
             return Err(error!(LotteryError::LotteryAlreadyEnded));
         }
@@ -167,58 +368,342 @@ pub mod scheduled_lottery_request {
             return Err(error!(LotteryError::LotteryActive));
         }
 
-        // TODO: verify winner bought a ticket OR is the lottery authority (if no entries)
+        // Derive the winner from the enclave's randomness so anyone can verify the drawing from
+        // the instruction data and the lottery's tickets.
+        let winner = ctx.accounts.lottery.load()?.select_winner(&randomness);
+        if ctx.accounts.winner.key() != winner {
+            return Err(error!(LotteryError::InvalidWinner));
+        }
 
         let lottery_authority = ctx.accounts.lottery.load()?.authority;
+        let lottery_id = ctx.accounts.lottery.load()?.lottery_id.to_le_bytes();
         let lottery_seeds = &[
             LOTTERY_SEED,
             lottery_authority.as_ref(),
+            &lottery_id,
             &[ctx.accounts.lottery.load()?.bump],
         ];
 
-        // Close the Switchboard request account and its associated token wallet.
-        // This will send all funds to the winner.
-        let close_ctx = FunctionRequestClose {
+        // A recurring lottery schedules its next round on the same Switchboard request, as long as
+        // the jackpot can top up the request escrow to cover the oracle bounty. The top up is paid
+        // out of the jackpot, so the protocol fee and the winner's payout are taken from the rest.
+        let program_state = ctx.accounts.program_state.load()?;
+        let (bounty, slots_until_expiration) = (
+            program_state.default_bounty,
+            program_state.slots_until_expiration,
+        );
+        drop(program_state);
+        let request_escrow_top_up =
+            bounty.saturating_sub(ctx.accounts.switchboard_request_escrow.amount);
+        let reschedule = ctx.accounts.lottery.load()?.recurring
+            && request_escrow_top_up <= ctx.accounts.escrow.amount;
+
+        if reschedule {
+            if request_escrow_top_up > 0 {
+                anchor_spl::token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: ctx.accounts.escrow.to_account_info(),
+                            to: ctx.accounts.switchboard_request_escrow.to_account_info(),
+                            authority: ctx.accounts.lottery.to_account_info(),
+                        },
+                        &[lottery_seeds],
+                    ),
+                    request_escrow_top_up,
+                )?;
+                ctx.accounts.escrow.reload()?;
+            }
+        } else {
+            // Close the Switchboard request account and its associated token wallet.
+            // This will send all funds to the winner.
+            let close_ctx = FunctionRequestClose {
+                request: ctx.accounts.switchboard_request.to_account_info(),
+                authority: ctx.accounts.lottery.to_account_info(),
+                escrow: ctx.accounts.switchboard_request_escrow.to_account_info(),
+                function: ctx.accounts.switchboard_function.to_account_info(),
+                sol_dest: ctx.accounts.winner.to_account_info(),
+                escrow_dest: ctx.accounts.escrow.to_account_info(),
+                state: ctx.accounts.switchboard_state.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            };
+            close_ctx.invoke_signed(
+                ctx.accounts.switchboard.clone(),
+                Some(true),
+                &[lottery_seeds],
+            )?;
+        }
+
+        // Unwrap the jackpot into the lottery account so the protocol fee can be split off before
+        // the rest is sent to the winner.
+        let lottery_info = ctx.accounts.lottery.to_account_info();
+        let lottery_lamports = lottery_info.lamports();
+        anchor_spl::token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.escrow.to_account_info(),
+                destination: lottery_info.clone(),
+                authority: lottery_info.clone(),
+            },
+            &[lottery_seeds],
+        ))?;
+        let unwrapped_lamports = lottery_info.lamports() - lottery_lamports;
+
+        let jackpot = ctx.accounts.escrow.amount;
+        // The escrow's rent is kept to re-create it when the lottery is rescheduled.
+        let escrow_rent = if reschedule {
+            unwrapped_lamports.saturating_sub(jackpot)
+        } else {
+            0
+        };
+        let payout_lamports = unwrapped_lamports - escrow_rent;
+        let fee = ctx
+            .accounts
+            .program_state
+            .load()?
+            .fee(jackpot)?
+            .min(payout_lamports);
+        **lottery_info.try_borrow_mut_lamports()? -= unwrapped_lamports;
+        **ctx
+            .accounts
+            .treasury
+            .to_account_info()
+            .try_borrow_mut_lamports()? += fee;
+        **ctx.accounts.winner.try_borrow_mut_lamports()? += payout_lamports - fee;
+        if fee > 0 {
+            let mut treasury = ctx.accounts.treasury.load_mut()?;
+            treasury.total_collected = treasury.total_collected.saturating_add(fee);
+        }
+
+        let round = ctx.accounts.lottery.load()?.round;
+        emit!(LotteryWinnerSelected {
+            lottery: ctx.accounts.lottery.key(),
+            round,
+            winner,
+            randomness,
+            jackpot,
+            fee,
+            request_escrow_top_up: if reschedule { request_escrow_top_up } else { 0 },
+            settled_slot: Clock::get()?.slot,
+            settled_timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        if !reschedule {
+            let mut lottery = ctx.accounts.lottery.load_mut()?;
+            lottery.has_ended = true;
+            lottery.winner = winner;
+
+            return Ok(());
+        }
+
+        // Re-create the lottery escrow for the next round. The vault is a system owned PDA that
+        // funds the escrow's rent, since the lottery account can't pay for new accounts.
+        let lottery_key = ctx.accounts.lottery.key();
+        let lottery_vault_seeds: &[&[u8]] = &[
+            LOTTERY_VAULT_SEED,
+            lottery_key.as_ref(),
+            &[ctx.bumps.lottery_vault],
+        ];
+        **ctx.accounts.lottery_vault.try_borrow_mut_lamports()? += escrow_rent;
+        anchor_spl::associated_token::create(CpiContext::new_with_signer(
+            ctx.accounts.associated_token_program.to_account_info(),
+            anchor_spl::associated_token::Create {
+                payer: ctx.accounts.lottery_vault.to_account_info(),
+                associated_token: ctx.accounts.escrow.to_account_info(),
+                authority: ctx.accounts.lottery.to_account_info(),
+                mint: ctx.accounts.switchboard_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+            &[lottery_vault_seeds],
+        ))?;
+
+        // Then schedule the request to execute when the next round concludes.
+        let duration_slots = ctx.accounts.lottery.load()?.duration_slots;
+        let open_slot = Clock::get()?.slot;
+        let close_slot = open_slot + u64::from(duration_slots);
+        let trigger_ctx = FunctionRequestTrigger {
             request: ctx.accounts.switchboard_request.to_account_info(),
             authority: ctx.accounts.lottery.to_account_info(),
             escrow: ctx.accounts.switchboard_request_escrow.to_account_info(),
             function: ctx.accounts.switchboard_function.to_account_info(),
-            sol_dest: ctx.accounts.winner.to_account_info(),
-            escrow_dest: ctx.accounts.escrow.to_account_info(),
             state: ctx.accounts.switchboard_state.to_account_info(),
+            attestation_queue: ctx.accounts.switchboard_attestation_queue.to_account_info(),
+            payer: ctx.accounts.lottery_vault.to_account_info(),
             token_program: ctx.accounts.token_program.to_account_info(),
             system_program: ctx.accounts.system_program.to_account_info(),
         };
-        close_ctx.invoke_signed(
+        trigger_ctx.invoke_signed(
             ctx.accounts.switchboard.clone(),
-            Some(true),
-            &[lottery_seeds],
+            Some(bounty),
+            Some(slots_until_expiration),
+            Some(close_slot),
+            &[lottery_seeds, lottery_vault_seeds],
         )?;
 
-        anchor_spl::token::close_account(CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            CloseAccount {
-                account: ctx.accounts.escrow.to_account_info(),
-                destination: ctx.accounts.winner.to_account_info(),
-                authority: ctx.accounts.lottery.to_account_info(),
-            },
-            &[&[
+        // Reset the lottery for the next round, archiving this round's winner.
+        let mut lottery = ctx.accounts.lottery.load_mut()?;
+        lottery.previous_winner = winner;
+        lottery.winner = Pubkey::default();
+        lottery.num_tickets = 0;
+        lottery.round += 1;
+        lottery.open_slot = open_slot;
+        lottery.close_slot = close_slot;
+
+        emit!(LotteryRoundScheduled {
+            lottery: lottery_key,
+            round: lottery.round,
+            open_slot,
+            close_slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_refund(ctx: Context<ClaimRefund>, ticket_index: u32) -> anchor_lang::Result<()> {
+        let lottery_info = ctx.accounts.lottery.to_account_info();
+
+        if !ctx.accounts.lottery.load()?.refunding {
+            if ctx.accounts.lottery.load()?.has_ended {
+                return Err(error!(LotteryError::LotteryAlreadyEnded));
+            }
+
+            let refund_slot = ctx
+                .accounts
+                .lottery
+                .load()?
+                .close_slot
+                .saturating_add(ctx.accounts.program_state.load()?.refund_delay_slots);
+            if refund_slot > Clock::get()?.slot {
+                return Err(error!(LotteryError::RefundNotAvailable));
+            }
+
+            let lottery_authority = ctx.accounts.lottery.load()?.authority;
+            let lottery_id = ctx.accounts.lottery.load()?.lottery_id.to_le_bytes();
+            let lottery_seeds = &[
                 LOTTERY_SEED,
-                ctx.accounts.lottery.load()?.authority.key().as_ref(),
+                lottery_authority.as_ref(),
+                &lottery_id,
                 &[ctx.accounts.lottery.load()?.bump],
-            ]],
-        ))?;
+            ];
+
+            // Close the Switchboard request so the lottery can no longer be drawn, returning its
+            // rent to the lottery authority and its escrow balance to the lottery escrow.
+            let close_ctx = FunctionRequestClose {
+                request: ctx.accounts.switchboard_request.to_account_info(),
+                authority: lottery_info.clone(),
+                escrow: ctx.accounts.switchboard_request_escrow.to_account_info(),
+                function: ctx.accounts.switchboard_function.to_account_info(),
+                sol_dest: ctx.accounts.authority.to_account_info(),
+                escrow_dest: ctx.accounts.escrow.to_account_info(),
+                state: ctx.accounts.switchboard_state.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            };
+            close_ctx.invoke_signed(
+                ctx.accounts.switchboard.clone(),
+                Some(true),
+                &[lottery_seeds],
+            )?;
+
+            // Unwrap every ticket's entry fee into the lottery account on the first refund.
+            let lottery_lamports = lottery_info.lamports();
+            anchor_spl::token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.escrow.to_account_info(),
+                    destination: lottery_info.clone(),
+                    authority: lottery_info.clone(),
+                },
+                &[lottery_seeds],
+            ))?;
+            let unwrapped_lamports = lottery_info.lamports() - lottery_lamports;
+
+            // Everything beyond the refundable entry fees, the request escrow's balance and the
+            // escrow's rent, goes back to the lottery authority along with the vault's lamports.
+            let refundable = {
+                let lottery = ctx.accounts.lottery.load()?;
+                lottery
+                    .entry_fee
+                    .checked_mul(u64::from(lottery.num_tickets))
+                    .ok_or(error!(LotteryError::MathOverflow))?
+            };
+            let leftover = unwrapped_lamports.saturating_sub(refundable);
+            **lottery_info.try_borrow_mut_lamports()? -= leftover;
+            **ctx.accounts.authority.try_borrow_mut_lamports()? += leftover;
+
+            let vault_lamports = ctx.accounts.lottery_vault.lamports();
+            if vault_lamports > 0 {
+                let lottery_key = ctx.accounts.lottery.key();
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.lottery_vault.to_account_info(),
+                            to: ctx.accounts.authority.to_account_info(),
+                        },
+                        &[&[
+                            LOTTERY_VAULT_SEED,
+                            lottery_key.as_ref(),
+                            &[ctx.bumps.lottery_vault],
+                        ]],
+                    ),
+                    vault_lamports,
+                )?;
+            }
+
+            let mut lottery = ctx.accounts.lottery.load_mut()?;
+            lottery.has_ended = true;
+            lottery.refunding = true;
+        }
 
         let mut lottery = ctx.accounts.lottery.load_mut()?;
-        lottery.has_ended = true;
-        lottery.winner = winner;
+        if lottery.sold_tickets().get(ticket_index as usize) != Some(&ctx.accounts.payer.key()) {
+            return Err(error!(LotteryError::InvalidTicket));
+        }
+        // Clear the ticket so it can only be refunded once.
+        lottery.tickets[ticket_index as usize] = Pubkey::default();
 
-        emit!(LotteryWinnerSelected {
+        let amount = lottery.entry_fee;
+        **lottery_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.payer.try_borrow_mut_lamports()? += amount;
+
+        emit!(LotteryRefunded {
             lottery: ctx.accounts.lottery.key(),
-            winner,
-            jackpot: ctx.accounts.escrow.amount,
-            settled_slot: Clock::get()?.slot,
-            settled_timestamp: Clock::get()?.unix_timestamp,
+            user: ctx.accounts.payer.key(),
+            ticket_index,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> anchor_lang::Result<()> {
+        let mut treasury = ctx.accounts.treasury.load_init()?;
+        treasury.bump = ctx.bumps.treasury;
+
+        Ok(())
+    }
+
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> anchor_lang::Result<()> {
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        let rent_exempt_lamports = Rent::get()?.minimum_balance(treasury_info.data_len());
+        if amount == 0
+            || treasury_info
+                .lamports()
+                .saturating_sub(rent_exempt_lamports)
+                < amount
+        {
+            return Err(error!(LotteryError::InsufficientFees));
+        }
+
+        **treasury_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.destination.try_borrow_mut_lamports()? += amount;
+
+        emit!(FeesWithdrawn {
+            destination: ctx.accounts.destination.key(),
+            amount,
         });
 
         Ok(())
@@ -235,6 +720,61 @@ pub struct ProgramState {
     pub authority: Pubkey,
     /// Switchboard Function pubkey.
     pub switchboard_function: Pubkey,
+    /// Account proposed to take over as authority, or the default pubkey if none.
+    pub pending_authority: Pubkey,
+    /// Pauses every pausable instruction when set.
+    pub paused: bool,
+    /// Bitmask of individually paused instructions, see the `PAUSE_*` constants.
+    pub paused_ixs: u8,
+    /// The number of slots a lottery runs for when created without a duration.
+    pub lottery_duration_slots: u32,
+    /// The bounty, in lamports, paid to oracles to draw each lottery.
+    pub default_bounty: u64,
+    /// The number of slots each lottery's Switchboard request can be processed in once it's valid.
+    pub slots_until_expiration: u64,
+    /// The protocol fee, in basis points, taken from each jackpot and sent to the treasury.
+    pub fee_bps: u16,
+    /// The number of slots after a lottery closes before its tickets can be refunded if undrawn.
+    pub refund_delay_slots: u64,
+}
+
+impl ProgramState {
+    /// Fails if the program, or the instruction with the given `PAUSE_*` bit, is paused.
+    pub fn assert_not_paused(&self, ix: u8) -> anchor_lang::Result<()> {
+        if self.paused || self.paused_ixs & ix != 0 {
+            return Err(error!(LotteryError::ProgramPaused));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the protocol fee taken from the given jackpot.
+    pub fn fee(&self, jackpot: u64) -> anchor_lang::Result<u64> {
+        u64::try_from(u128::from(jackpot) * u128::from(self.fee_bps) / u128::from(MAX_FEE_BPS))
+            .map_err(|_| error!(LotteryError::MathOverflow))
+    }
+}
+
+/// Represents the treasury which collects the protocol fee.
+/// The treasury's lamports, less rent, can be withdrawn by the program authority.
+#[account(zero_copy(unsafe))]
+pub struct Treasury {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// The sum of all protocol fees collected, in lamports.
+    pub total_collected: u64,
+}
+
+/// Represents an authority hosting lotteries.
+/// Each lottery is seeded by the host's authority and a sequential lottery id.
+#[account(zero_copy(unsafe))]
+pub struct LotteryHost {
+    /// PDA bump seed.
+    pub bump: u8,
+    /// Account authorized to create lotteries.
+    pub authority: Pubkey,
+    /// The id assigned to the next lottery created by this host.
+    pub next_lottery_id: u64,
 }
 
 /// Represents the state of a lottery
@@ -267,6 +807,43 @@ pub struct LotteryState {
 
     // Data
     pub tickets: [Pubkey; MAX_TICKETS],
+
+    /// The lottery's id within its LotteryHost, used in the PDA seeds.
+    pub lottery_id: u64,
+
+    // Recurring config
+    /// Whether draw_winner schedules another round after paying out.
+    pub recurring: bool,
+    /// The number of slots each round lasts.
+    pub duration_slots: u32,
+    /// The current round, starting at 0.
+    pub round: u32,
+    /// The winner of the previous round.
+    pub previous_winner: Pubkey,
+
+    /// Whether the lottery was never drawn and its tickets are being refunded.
+    pub refunding: bool,
+}
+impl LotteryState {
+    /// The tickets sold so far. Only the first `num_tickets` entries of `tickets` are set.
+    pub fn sold_tickets(&self) -> &[Pubkey] {
+        let num_tickets = (self.num_tickets as usize).min(MAX_TICKETS);
+        &self.tickets[..num_tickets]
+    }
+
+    /// Selects the winning ticket as `tickets[randomness % num_tickets]`, or the lottery
+    /// authority if no tickets were sold.
+    pub fn select_winner(&self, randomness: &[u8; 32]) -> Pubkey {
+        let tickets = self.sold_tickets();
+        if tickets.is_empty() {
+            return self.authority;
+        }
+
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&randomness[..8]);
+        let index = u64::from_le_bytes(bytes) % tickets.len() as u64;
+        tickets[index as usize]
+    }
 }
 
 #[derive(Accounts)]
@@ -283,8 +860,18 @@ pub struct Initialize<'info> {
         bump
     )]
     pub program_state: AccountLoader<'info, ProgramState>,
-    /// CHECK: an account authorized to change the program config.
-    pub authority: AccountInfo<'info>,
+    /// The program's upgrade authority, which becomes the authority of the program config.
+    pub authority: Signer<'info>,
+
+    // UPGRADE AUTHORITY ACCOUNTS
+    // Only the upgrade authority can initialize the program so the deploy can't be front-run.
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::ScheduledLotteryRequest>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ LotteryError::InvalidAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
 
     // SWITCHBOARD ACCOUNTS
     #[account(
@@ -298,6 +885,79 @@ pub struct Initialize<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetFunction<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    // SWITCHBOARD ACCOUNTS
+    #[account(
+        constraint =
+            // Ensure custom requests are allowed
+            switchboard_function.load()?.requests_disabled == 0
+    )]
+    pub switchboard_function: AccountLoader<'info, FunctionAccountData>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = pending_authority @ LotteryError::InvalidAuthority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub pending_authority: Signer<'info>,
+}
+
 #[derive(Accounts)]
 pub struct CreateLottery<'info> {
     #[account(mut)]
@@ -311,11 +971,23 @@ pub struct CreateLottery<'info> {
     )]
     pub program_state: AccountLoader<'info, ProgramState>,
 
+    #[account(
+        mut,
+        seeds = [LOTTERY_HOST_SEED, authority.key().as_ref()],
+        bump = lottery_host.load()?.bump,
+        has_one = authority,
+    )]
+    pub lottery_host: AccountLoader<'info, LotteryHost>,
+
     #[account(
         init,
         payer = payer,
         space = 8 + std::mem::size_of::<LotteryState>(),
-        seeds = [LOTTERY_SEED, authority.key().as_ref()],
+        seeds = [
+            LOTTERY_SEED,
+            authority.key().as_ref(),
+            &lottery_host.load()?.next_lottery_id.to_le_bytes(),
+        ],
         bump
     )]
     pub lottery: AccountLoader<'info, LotteryState>,
@@ -328,8 +1000,8 @@ pub struct CreateLottery<'info> {
     )]
     pub lottery_escrow: Account<'info, TokenAccount>,
 
-    /// CHECK: an account authorized to change the lottery config.
-    pub authority: AccountInfo<'info>,
+    /// An account authorized to change the lottery config.
+    pub authority: Signer<'info>,
 
     // SWITCHBOARD ACCOUNTS
     /// CHECK: program ID checked.
@@ -376,11 +1048,37 @@ pub struct CreateLottery<'info> {
     pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
+#[derive(Accounts)]
+pub struct InitializeLotteryHost<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<LotteryHost>(),
+        payer = payer,
+        seeds = [LOTTERY_HOST_SEED, authority.key().as_ref()],
+        bump
+    )]
+    pub lottery_host: AccountLoader<'info, LotteryHost>,
+    pub authority: Signer<'info>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct BuyTicket<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
 
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+
     #[account(
         mut,
         has_one = escrow,
@@ -398,6 +1096,7 @@ pub struct BuyTicket<'info> {
 pub struct DrawWinner<'info> {
     #[account(
         mut,
+        has_one = escrow,
         has_one = switchboard_request,
     )]
     pub lottery: AccountLoader<'info, LotteryState>,
@@ -405,7 +1104,7 @@ pub struct DrawWinner<'info> {
     #[account(mut)]
     pub escrow: Box<Account<'info, TokenAccount>>,
 
-    /// CHECK: we should validate this is owned by the SystemProgram
+    /// CHECK: checked against the ticket selected by the randomness in draw_winner.
     #[account(mut)]
     pub winner: AccountInfo<'info>,
 
@@ -420,6 +1119,7 @@ pub struct DrawWinner<'info> {
       )]
     pub switchboard_state: AccountLoader<'info, AttestationProgramState>,
     #[account(
+        mut,
         constraint = switchboard_function.load()?.validate_request(
             &switchboard_request,
             &enclave_signer.to_account_info()
@@ -437,12 +1137,148 @@ pub struct DrawWinner<'info> {
     pub switchboard_request: Box<Account<'info, FunctionRequestAccountData>>,
     pub enclave_signer: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        address = switchboard_request.escrow,
+    )]
     pub switchboard_request_escrow: Box<Account<'info, TokenAccount>>,
 
     // SYSTEM ACCOUNTS
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
+
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED, TREASURY_SEED],
+        bump = treasury.load()?.bump,
+    )]
+    pub treasury: AccountLoader<'info, Treasury>,
+
+    // RECURRING LOTTERY ACCOUNTS
+    // Used to re-create the escrow and re-trigger the request when a recurring lottery is
+    // rescheduled.
+    pub switchboard_attestation_queue: AccountLoader<'info, AttestationQueueAccountData>,
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub switchboard_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        seeds = [LOTTERY_VAULT_SEED, lottery.key().as_ref()],
+        bump,
+    )]
+    pub lottery_vault: SystemAccount<'info>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRefund<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+
+    #[account(
+        mut,
+        has_one = escrow,
+        has_one = authority,
+        has_one = switchboard_request,
+    )]
+    pub lottery: AccountLoader<'info, LotteryState>,
+
+    /// CHECK: the lottery's escrow, closed by the first refund.
+    #[account(mut)]
+    pub escrow: AccountInfo<'info>,
+
+    /// The lottery authority, which reclaims the lottery's leftover lamports on the first refund.
+    #[account(mut)]
+    pub authority: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [LOTTERY_VAULT_SEED, lottery.key().as_ref()],
+        bump,
+    )]
+    pub lottery_vault: SystemAccount<'info>,
+
+    // SWITCHBOARD ACCOUNTS
+    /// CHECK: program ID checked.
+    #[account(executable, address = SWITCHBOARD_ATTESTATION_PROGRAM_ID)]
+    pub switchboard: AccountInfo<'info>,
+    /// CHECK: validated by the Switchboard program when the request is closed.
+    pub switchboard_state: AccountInfo<'info>,
+    /// CHECK: validated by the Switchboard program when the request is closed.
+    #[account(mut)]
+    pub switchboard_function: AccountInfo<'info>,
+    /// CHECK: closed by the first refund, checked against the lottery.
+    #[account(mut)]
+    pub switchboard_request: AccountInfo<'info>,
+    /// CHECK: closed by the first refund, validated by the Switchboard program.
+    #[account(mut)]
+    pub switchboard_request_escrow: AccountInfo<'info>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        init,
+        space = 8 + std::mem::size_of::<Treasury>(),
+        payer = payer,
+        seeds = [PROGRAM_SEED, TREASURY_SEED],
+        bump
+    )]
+    pub treasury: AccountLoader<'info, Treasury>,
+
+    // SYSTEM ACCOUNTS
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    // PROGRAM ACCOUNTS
+    #[account(
+        seeds = [PROGRAM_SEED],
+        bump = program_state.load()?.bump,
+        has_one = authority,
+    )]
+    pub program_state: AccountLoader<'info, ProgramState>,
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_SEED, TREASURY_SEED],
+        bump = treasury.load()?.bump,
+    )]
+    pub treasury: AccountLoader<'info, Treasury>,
+
+    /// CHECK: receives the withdrawn fees
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
 }
 
 #[error_code]
@@ -456,6 +1292,35 @@ pub enum LotteryError {
     LotterySoldOut,
     #[msg("Lottery is active and cannot be closed")]
     LotteryActive,
+    #[msg("Program is paused")]
+    ProgramPaused,
+    #[msg("Lottery duration must be greater than 0 slots")]
+    InvalidLotteryDuration,
+    #[msg("Request expiration is below the minimum Switchboard request expiration")]
+    InvalidRequestExpiration,
+    #[msg("Protocol fee exceeds the maximum")]
+    InvalidFee,
+    #[msg("Treasury can't cover the withdrawal")]
+    InsufficientFees,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("Winner does not match the ticket selected by the randomness")]
+    InvalidWinner,
+    #[msg("Refund delay must be greater than the request expiration")]
+    InvalidRefundDelay,
+    #[msg("Lottery can't be refunded until the refund delay has passed")]
+    RefundNotAvailable,
+    #[msg("Ticket wasn't bought by this account or was already refunded")]
+    InvalidTicket,
+}
+
+#[event]
+pub struct LotteryCreated {
+    pub lottery: Pubkey,
+    pub authority: Pubkey,
+    pub lottery_id: u64,
+    pub entry_fee: u64,
+    pub close_slot: u64,
 }
 
 #[event]
@@ -469,8 +1334,121 @@ pub struct LotteryTicketPurchased {
 #[event]
 pub struct LotteryWinnerSelected {
     pub lottery: Pubkey,
+    pub round: u32,
     pub winner: Pubkey,
+    pub randomness: [u8; 32],
     pub jackpot: u64,
+    pub fee: u64,
+    pub request_escrow_top_up: u64,
     pub settled_timestamp: i64,
     pub settled_slot: u64,
 }
+
+#[event]
+pub struct LotteryRefunded {
+    pub lottery: Pubkey,
+    pub user: Pubkey,
+    pub ticket_index: u32,
+    pub amount: u64,
+}
+
+#[event]
+pub struct LotteryRoundScheduled {
+    pub lottery: Pubkey,
+    pub round: u32,
+    pub open_slot: u64,
+    pub close_slot: u64,
+}
+
+#[event]
+pub struct SwitchboardFunctionUpdated {
+    pub old_function: Pubkey,
+    pub new_function: Pubkey,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityAccepted {
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lottery_with_tickets(num_tickets: u32) -> LotteryState {
+        let mut lottery: LotteryState = anchor_lang::__private::bytemuck::Zeroable::zeroed();
+        lottery.authority = Pubkey::new_unique();
+        for ticket in lottery.tickets.iter_mut().take(num_tickets as usize) {
+            *ticket = Pubkey::new_unique();
+        }
+        lottery.num_tickets = num_tickets;
+        lottery
+    }
+
+    fn randomness(seed: u64) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&seed.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_select_winner_with_no_tickets() {
+        let lottery = lottery_with_tickets(0);
+
+        for seed in 0..10 {
+            assert_eq!(lottery.select_winner(&randomness(seed)), lottery.authority);
+        }
+    }
+
+    #[test]
+    fn test_select_winner_with_one_ticket() {
+        let lottery = lottery_with_tickets(1);
+
+        for seed in [0, 1, 255, u64::MAX] {
+            assert_eq!(lottery.select_winner(&randomness(seed)), lottery.tickets[0]);
+        }
+    }
+
+    #[test]
+    fn test_select_winner_with_many_tickets() {
+        let lottery = lottery_with_tickets(10);
+
+        for seed in 0..1000 {
+            let winner = lottery.select_winner(&randomness(seed));
+            assert!(lottery.sold_tickets().contains(&winner));
+            assert_ne!(winner, Pubkey::default());
+        }
+
+        // Every sold ticket can win
+        for (i, ticket) in lottery.sold_tickets().iter().enumerate() {
+            assert_eq!(lottery.select_winner(&randomness(i as u64)), *ticket);
+        }
+    }
+
+    #[test]
+    fn test_select_winner_when_sold_out() {
+        let lottery = lottery_with_tickets(MAX_TICKETS as u32);
+
+        assert_eq!(
+            lottery.select_winner(&randomness(MAX_TICKETS as u64 - 1)),
+            lottery.tickets[MAX_TICKETS - 1]
+        );
+        assert_eq!(
+            lottery.select_winner(&randomness(MAX_TICKETS as u64)),
+            lottery.tickets[0]
+        );
+    }
+}
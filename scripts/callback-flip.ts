@@ -25,6 +25,8 @@ interface UserGuessSettledEvent {
   userGuess: number;
   result: number;
   userWon: boolean;
+  wager: anchor.BN;
+  payout: anchor.BN;
   requestTimestamp: anchor.BN;
   settledTimestamp: anchor.BN;
 }
@@ -204,6 +206,13 @@ interface UserGuessSettledEvent {
           payer: payer.publicKey,
           programState: programStatePubkey,
           authority: payer.publicKey,
+          program: program.programId,
+          programData: anchor.web3.PublicKey.findProgramAddressSync(
+            [program.programId.toBytes()],
+            new anchor.web3.PublicKey(
+              "BPFLoaderUpgradeab1e11111111111111111111111"
+            )
+          )[0],
           switchboardFunction: switchboardFunction.publicKey,
         })
         .rpc();
@@ -229,6 +238,65 @@ interface UserGuessSettledEvent {
     });
   }
 
+  const [housePubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("HOUSE_VAULT")],
+    program.programId
+  );
+  console.log(`HOUSE: ${housePubkey}`);
+  if (!(await program.provider.connection.getAccountInfo(housePubkey))) {
+    const [shareMintPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("HOUSE_SHARE_MINT")],
+      program.programId
+    );
+    const houseInitTx = await program.methods
+      .initializeHouse(0, 10000)
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        house: housePubkey,
+        shareMint: shareMintPubkey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_house: ${houseInitTx}`);
+  }
+
+  const [statsPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("SIMPLE_RANDOMNESS"), Buffer.from("GLOBAL_STATS")],
+    program.programId
+  );
+  console.log(`STATS: ${statsPubkey}`);
+  if (!(await program.provider.connection.getAccountInfo(statsPubkey))) {
+    const statsInitTx = await program.methods
+      .initializeStats()
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        stats: statsPubkey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_stats: ${statsInitTx}`);
+  }
+
+  const [treasuryPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("SIMPLE_RANDOMNESS"), Buffer.from("TREASURY")],
+    program.programId
+  );
+  console.log(`TREASURY: ${treasuryPubkey}`);
+  if (!(await program.provider.connection.getAccountInfo(treasuryPubkey))) {
+    const treasuryInitTx = await program.methods
+      .initializeTreasury()
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        treasury: treasuryPubkey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_treasury: ${treasuryInitTx}`);
+  }
+
   const [userPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
     [Buffer.from("RANDOMNESS_USER"), payer.publicKey.toBytes()],
     program.programId
@@ -291,6 +359,7 @@ interface UserGuessSettledEvent {
           programState: programStatePubkey,
           user: userPubkey,
           authority: payer.publicKey,
+          stats: statsPubkey,
           switchboard: switchboardProgram.attestationProgramId,
           switchboardState:
             switchboardProgram.attestationProgramState.publicKey,
@@ -358,11 +427,15 @@ interface UserGuessSettledEvent {
     "UserGuessSettled",
     async (meter) => {
       program.methods
-        .guess(1)
+        .guess(1, new anchor.BN(0), 1, 10, null)
         .accounts({
           payer: payer.publicKey,
+          programState: programStatePubkey,
           user: userPubkey,
           authority: payer.publicKey,
+          signer: payer.publicKey,
+          house: housePubkey,
+          stats: statsPubkey,
           switchboard: switchboardProgram.attestationProgramId,
           switchboardState:
             switchboardProgram.attestationProgramState.publicKey,
@@ -26,6 +26,8 @@ interface UserGuessSettledEvent {
   userGuess: number;
   result: number;
   userWon: boolean;
+  wager: anchor.BN;
+  payout: anchor.BN;
   requestTimestamp: anchor.BN;
   settledTimestamp: anchor.BN;
 }
@@ -190,6 +192,23 @@ interface CostReceipt {
     program.programId
   );
   console.log(`USER: ${userPubkey}`);
+
+  const [housePubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("HOUSE_VAULT")],
+    program.programId
+  );
+  console.log(`HOUSE: ${housePubkey}`);
+  if (!(await program.provider.connection.getAccountInfo(housePubkey))) {
+    const houseInitTx = await program.methods
+      .initializeHouse(0, 10000)
+      .accounts({
+        payer: payer.publicKey,
+        house: housePubkey,
+        authority: payer.publicKey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_house: ${houseInitTx}`);
+  }
   const initialUserAccountInfo =
     await program.provider.connection.getAccountInfo(userPubkey);
 
@@ -213,9 +232,10 @@ interface CostReceipt {
     "UserGuessSettled",
     async (meter) => {
       const tx = await program.methods
-        .guess(1)
+        .guess(1, new anchor.BN(0), 1, 10)
         .accounts({
           payer: payer.publicKey,
+          house: housePubkey,
           user: userPubkey,
           authority: payer.publicKey,
           switchboard: switchboardProgram.attestationProgramId,
@@ -8,6 +8,10 @@ use switchboard_solana::sb_error;
 mod params;
 pub use params::*;
 
+pub const HOUSE_SEED: &[u8] = b"HOUSE_VAULT";
+pub const PROGRAM_SEED: &[u8] = b"SIMPLE_RANDOMNESS";
+pub const TREASURY_SEED: &[u8] = b"TREASURY";
+
 #[switchboard_function]
 pub async fn sb_function(runner: FunctionRunner, params: Vec<u8>) -> Result<Vec<Instruction>, SbFunctionError> {
     // parse and validate user provided request params
@@ -20,23 +24,45 @@ pub async fn sb_function(runner: FunctionRunner, params: Vec<u8>) -> Result<Vec<
     // LEN: 12 bytes
     // [0-8]: Anchor Ixn Discriminator
     // [9-12]: Random Result as u32
-    let mut ixn_data = get_ixn_discriminator("settle").to_vec();
+    let ixn_name = if params.is_ticket { "settle_ticket" } else { "settle" };
+    let mut ixn_data = get_ixn_discriminator(ixn_name).to_vec();
     ixn_data.append(&mut random_bytes);
 
     // ACCOUNTS:
-    // 1. User (mut): our user who guessed
+    // 1. User or Ticket (mut): our user or ticket who guessed
     // 2. Switchboard Function
     // 3. Switchboard Function Request
     // 4. Enclave Signer (signer): our Gramine generated keypair
+    // 5. House (mut): the house vault paying out winnings, only if AUTHORITY was provided
+    // 6. Authority (mut): the user's authority receiving winnings, only if AUTHORITY was provided
+    // 7. Stats (mut): the program-wide statistics account, only if STATS was provided
+    // 8. Program State: the program config, only if PROGRAM_STATE was provided
+    // 9. Treasury (mut): collects the protocol fee, only if PROGRAM_STATE was provided
+    let mut accounts = vec![
+        AccountMeta::new(params.user_key, false),
+        AccountMeta::new_readonly(runner.function, false),
+        AccountMeta::new_readonly(runner.function_request_key.unwrap(), false),
+        AccountMeta::new_readonly(runner.signer, true),
+    ];
+    if let Some(authority_key) = params.authority_key {
+        let (house_key, _) = Pubkey::find_program_address(&[HOUSE_SEED], &params.program_id);
+        accounts.push(AccountMeta::new(house_key, false));
+        accounts.push(AccountMeta::new(authority_key, false));
+    }
+    if let Some(stats_key) = params.stats_key {
+        accounts.push(AccountMeta::new(stats_key, false));
+    }
+    if let Some(program_state_key) = params.program_state_key {
+        accounts.push(AccountMeta::new_readonly(program_state_key, false));
+        let (treasury_key, _) =
+            Pubkey::find_program_address(&[PROGRAM_SEED, TREASURY_SEED], &params.program_id);
+        accounts.push(AccountMeta::new(treasury_key, false));
+    }
+
     Ok(vec![Instruction {
         program_id: params.program_id,
         data: ixn_data,
-        accounts: vec![
-            AccountMeta::new(params.user_key, false),
-            AccountMeta::new_readonly(runner.function, false),
-            AccountMeta::new_readonly(runner.function_request_key.unwrap(), false),
-            AccountMeta::new_readonly(runner.signer, true),
-        ],
+        accounts,
     }])
 }
 
@@ -53,14 +79,32 @@ fn generate_randomness(min: u32, max: u32) -> u32 {
         return generate_randomness(max, min);
     }
 
-    // We add one so its inclusive [min, max]
-    let window = (max + 1) - min;
+    // We add one so its inclusive [min, max]. The window only overflows for the full u32 range,
+    // where every raw result is already in bounds.
+    match (max - min).checked_add(1) {
+        Some(window) => sample_window(window, read_rand_u32) + min,
+        None => read_rand_u32(),
+    }
+}
 
+fn read_rand_u32() -> u32 {
     let mut bytes: [u8; 4] = [0u8; 4];
     Gramine::read_rand(&mut bytes).expect("gramine failed to generate randomness");
     let raw_result: &[u32] = bytemuck::cast_slice(&bytes[..]);
+    raw_result[0]
+}
 
-    (raw_result[0] % window) + min
+// Returns a uniform value in [0, window). Raw values at or above the largest multiple of the window
+// are redrawn, otherwise the modulo would favor the low end of large windows.
+fn sample_window(window: u32, mut next: impl FnMut() -> u32) -> u32 {
+    let range = 1u64 << 32;
+    let limit = range - range % u64::from(window);
+    loop {
+        let raw = next();
+        if u64::from(raw) < limit {
+            return raw % window;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -74,7 +118,7 @@ mod tests {
         let max = 50;
 
         let result = generate_randomness(100, 50);
-        assert!(result >= max && result < min);
+        assert!(result >= max && result <= min);
     }
 
     // 2. Check when lower_bound is equal to upper_bound
@@ -92,10 +136,19 @@ mod tests {
 
         let result = generate_randomness(min, max);
 
-        assert!(result >= min && result < max);
+        assert!(result >= min && result <= max);
     }
 
-    // 4. Test randomness distribution (not truly deterministic, but a sanity check)
+    // 4. Check the window can't overflow at the u32 limits
+    #[test]
+    fn test_generate_randomness_at_u32_limits() {
+        let result = generate_randomness(u32::MAX - 1, u32::MAX);
+        assert!(result >= u32::MAX - 1);
+
+        generate_randomness(0, u32::MAX);
+    }
+
+    // 5. Test randomness distribution (not truly deterministic, but a sanity check)
     #[test]
     fn test_generate_randomness_distribution() {
         let min = 0;
@@ -113,4 +166,50 @@ mod tests {
             assert!(*count > 0);
         }
     }
+
+    // 6. Check raw values past the last full window are redrawn instead of wrapping around
+    #[test]
+    fn test_sample_window_rejects_biased_values() {
+        let window = 3 << 30;
+        let mut raw = [u32::MAX, window, window - 1].into_iter();
+        assert_eq!(sample_window(window, || raw.next().unwrap()), window - 1);
+
+        // Every value is accepted when the window evenly divides the u32 range
+        let mut raw = [u32::MAX].into_iter();
+        assert_eq!(sample_window(2, || raw.next().unwrap()), 1);
+    }
+
+    // 7. Check a large window is uniform. A plain modulo would put half the results in the first
+    // third of this window.
+    #[test]
+    fn test_generate_randomness_large_window_distribution() {
+        let third = 1u32 << 30;
+        let max = 3 * third - 1;
+
+        let mut low = 0;
+        for _ in 0..3000 {
+            if generate_randomness(0, max) < third {
+                low += 1;
+            }
+        }
+
+        assert!((850..=1150).contains(&low), "{low} of 3000 results in the first third");
+    }
+
+    // 8. Check both bounds are reachable, since the on-chain settle ixn must accept every result
+    #[test]
+    fn test_generate_randomness_emits_inclusive_bounds() {
+        let min = 1;
+        let max = 10;
+
+        let mut counts = vec![0; (max + 1) as usize];
+        for _ in 0..1000 {
+            let result = generate_randomness(min, max);
+            assert!(result >= min && result <= max);
+            counts[result as usize] += 1;
+        }
+
+        assert!(counts[min as usize] > 0);
+        assert!(counts[max as usize] > 0);
+    }
 }
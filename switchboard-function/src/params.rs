@@ -5,6 +5,14 @@ pub struct ContainerParams {
     pub min_result: u32,
     pub max_result: u32,
     pub user_key: Pubkey,
+    /// Set when the request settles a nonce-seeded guess ticket rather than a user account.
+    pub is_ticket: bool,
+    /// The user's authority, required by programs that pay out winnings in settle.
+    pub authority_key: Option<Pubkey>,
+    /// The program-wide statistics account, required by programs that record stats in settle.
+    pub stats_key: Option<Pubkey>,
+    /// The program config account, required by programs that check the function in settle.
+    pub program_state_key: Option<Pubkey>,
 }
 
 impl ContainerParams {
@@ -15,6 +23,10 @@ impl ContainerParams {
         let mut min_result: u32 = 0;
         let mut max_result: u32 = 0;
         let mut user_key: Pubkey = Pubkey::default();
+        let mut is_ticket = false;
+        let mut authority_key: Option<Pubkey> = None;
+        let mut stats_key: Option<Pubkey> = None;
+        let mut program_state_key: Option<Pubkey> = None;
 
         for env_pair in params.split(',') {
             let pair: Vec<&str> = env_pair.splitn(2, '=').collect();
@@ -24,6 +36,15 @@ impl ContainerParams {
                     "MIN_RESULT" => min_result = pair[1].parse::<u32>().unwrap(),
                     "MAX_RESULT" => max_result = pair[1].parse::<u32>().unwrap(),
                     "USER" => user_key = Pubkey::from_str(pair[1]).unwrap(),
+                    "TICKET" => {
+                        user_key = Pubkey::from_str(pair[1]).unwrap();
+                        is_ticket = true;
+                    }
+                    "AUTHORITY" => authority_key = Some(Pubkey::from_str(pair[1]).unwrap()),
+                    "STATS" => stats_key = Some(Pubkey::from_str(pair[1]).unwrap()),
+                    "PROGRAM_STATE" => {
+                        program_state_key = Some(Pubkey::from_str(pair[1]).unwrap())
+                    }
                     _ => {}
                 }
             }
@@ -50,6 +71,10 @@ impl ContainerParams {
             min_result,
             max_result,
             user_key,
+            is_ticket,
+            authority_key,
+            stats_key,
+            program_state_key,
         })
     }
 }
@@ -75,5 +100,81 @@ mod tests {
         assert_eq!(params.min_result, 1);
         assert_eq!(params.max_result, 6);
         assert_eq!(params.user_key, anchor_spl::token::ID);
+        assert!(!params.is_ticket);
+        assert_eq!(params.authority_key, None);
+        assert_eq!(params.stats_key, None);
+        assert_eq!(params.program_state_key, None);
+    }
+
+    #[test]
+    fn test_params_decode_ticket() {
+        let request_params_string = format!(
+            "PID={},MIN_RESULT={},MAX_RESULT={},TICKET={},AUTHORITY={}",
+            anchor_spl::token::ID,
+            1,
+            6,
+            anchor_spl::token::ID,
+            anchor_spl::associated_token::ID
+        );
+        let request_params_bytes = request_params_string.into_bytes();
+
+        let params = ContainerParams::decode(&request_params_bytes).unwrap();
+
+        assert_eq!(params.user_key, anchor_spl::token::ID);
+        assert!(params.is_ticket);
+        assert_eq!(params.authority_key, Some(anchor_spl::associated_token::ID));
+    }
+
+    #[test]
+    fn test_params_decode_with_authority() {
+        let request_params_string = format!(
+            "PID={},MIN_RESULT={},MAX_RESULT={},USER={},AUTHORITY={}",
+            anchor_spl::token::ID,
+            1,
+            6,
+            anchor_spl::token::ID,
+            anchor_spl::associated_token::ID
+        );
+        let request_params_bytes = request_params_string.into_bytes();
+
+        let params = ContainerParams::decode(&request_params_bytes).unwrap();
+
+        assert_eq!(params.authority_key, Some(anchor_spl::associated_token::ID));
+    }
+
+    #[test]
+    fn test_params_decode_with_stats() {
+        let request_params_string = format!(
+            "PID={},MIN_RESULT={},MAX_RESULT={},USER={},AUTHORITY={},STATS={}",
+            anchor_spl::token::ID,
+            1,
+            6,
+            anchor_spl::token::ID,
+            anchor_spl::associated_token::ID,
+            anchor_spl::token::ID
+        );
+        let request_params_bytes = request_params_string.into_bytes();
+
+        let params = ContainerParams::decode(&request_params_bytes).unwrap();
+
+        assert_eq!(params.authority_key, Some(anchor_spl::associated_token::ID));
+        assert_eq!(params.stats_key, Some(anchor_spl::token::ID));
+    }
+
+    #[test]
+    fn test_params_decode_with_program_state() {
+        let request_params_string = format!(
+            "PID={},MIN_RESULT={},MAX_RESULT={},USER={},PROGRAM_STATE={}",
+            anchor_spl::token::ID,
+            1,
+            6,
+            anchor_spl::token::ID,
+            anchor_spl::associated_token::ID
+        );
+        let request_params_bytes = request_params_string.into_bytes();
+
+        let params = ContainerParams::decode(&request_params_bytes).unwrap();
+
+        assert_eq!(params.program_state_key, Some(anchor_spl::associated_token::ID));
     }
 }
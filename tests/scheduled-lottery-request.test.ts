@@ -11,7 +11,8 @@ import {
 } from "@switchboard-xyz/solana.js";
 import { parseRawMrEnclave, sleep } from "@switchboard-xyz/common";
 import { assert } from "chai";
-import { loadSwitchboard } from "./utils";
+import { randomBytes } from "crypto";
+import { getProgramDataAddress, loadSwitchboard } from "./utils";
 
 // This value doesnt matter for our tests because we are not validating
 // the execution off-chain.
@@ -36,8 +37,28 @@ describe("scheduled-lottery-request", () => {
     [Buffer.from("SIMPLE_LOTTERY")],
     program.programId
   );
+  const [lotteryHostPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("LOTTERY_HOST"), payer.publicKey.toBytes()],
+    program.programId
+  );
   const [lotteryPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
-    [Buffer.from("LOTTERY_STATE"), payer.publicKey.toBytes()],
+    [
+      Buffer.from("LOTTERY_STATE"),
+      payer.publicKey.toBytes(),
+      new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+  const [recurringLotteryPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("LOTTERY_STATE"),
+      payer.publicKey.toBytes(),
+      new anchor.BN(1).toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+  const [treasuryPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("SIMPLE_LOTTERY"), Buffer.from("TREASURY")],
     program.programId
   );
 
@@ -47,6 +68,7 @@ describe("scheduled-lottery-request", () => {
   let switchboard: BootstrappedAttestationQueue;
   let switchboardFunction: FunctionAccount;
   const switchboardRequestKeypair = anchor.web3.Keypair.generate();
+  const recurringRequestKeypair = anchor.web3.Keypair.generate();
 
   before(async () => {
     [switchboard, switchboardFunction] = await loadSwitchboard(
@@ -56,9 +78,73 @@ describe("scheduled-lottery-request", () => {
     );
   });
 
+  // Builds the function_request_verify ixn that must run before any of the
+  // instructions emitted by the Switchboard Function.
+  async function buildRequestVerifyIxn(
+    requestPubkey: anchor.web3.PublicKey,
+    enclaveSigner: anchor.web3.Keypair
+  ): Promise<anchor.web3.TransactionInstruction> {
+    // Load the Switchboard account states
+    const [_sbRequestAccount, sbRequestState] =
+      await FunctionRequestAccount.load(switchboard.program, requestPubkey);
+    const sbFunctionState = await switchboardFunction.loadData();
+
+    // We need a wrapped SOL TokenAccount to receive the oracle reward from the fn request escrow
+    const rewardAddress =
+      await switchboard.program.mint.getOrCreateAssociatedUser(payer.publicKey);
+
+    return attestationTypes.functionRequestVerify(
+      switchboard.program,
+      {
+        params: {
+          observedTime: new anchor.BN(Math.floor(Date.now() / 1000)),
+          errorCode: 0,
+          mrEnclave: Array.from(MRENCLAVE),
+          requestSlot: sbRequestState.activeRequest.requestSlot,
+          containerParamsHash: sbRequestState.containerParamsHash,
+        },
+      },
+      {
+        request: requestPubkey,
+        functionEnclaveSigner: enclaveSigner.publicKey,
+        escrow: sbRequestState.escrow,
+        function: switchboardFunction.publicKey,
+        functionEscrow: sbFunctionState.escrowTokenWallet,
+        verifierQuote: switchboard.verifier.publicKey,
+        verifierEnclaveSigner: switchboard.verifier.signer.publicKey,
+        verifierPermission: switchboard.verifier.permissionAccount.publicKey,
+        state: switchboard.program.attestationProgramState.publicKey,
+        attestationQueue: switchboard.attestationQueue.publicKey,
+        receiver: rewardAddress,
+        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+      }
+    );
+  }
+
   ///////////////////////////////////////////////////////
   // Initialize the program and set the Switchboard Function
   ///////////////////////////////////////////////////////
+  it("initialize rejects signers other than the upgrade authority", async () => {
+    const attacker = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .initialize()
+        .accounts({
+          payer: payer.publicKey,
+          programState: programStatePubkey,
+          authority: attacker.publicKey,
+          program: program.programId,
+          programData: getProgramDataAddress(program.programId),
+          switchboardFunction: switchboardFunction.publicKey,
+        })
+        .signers([attacker])
+        .rpc();
+      assert.fail("initialize should have required the upgrade authority");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidAuthority");
+    }
+  });
+
   it("initialize", async () => {
     const tx = await program.methods
       .initialize()
@@ -66,24 +152,208 @@ describe("scheduled-lottery-request", () => {
         payer: payer.publicKey,
         programState: programStatePubkey,
         authority: payer.publicKey,
+        program: program.programId,
+        programData: getProgramDataAddress(program.programId),
         switchboardFunction: switchboardFunction.publicKey,
       })
       .rpc();
     console.log(`[TX] initialize: ${tx}`);
   });
 
+  it("update_config", async () => {
+    // The refund delay must outlast the request expiration
+    try {
+      await program.methods
+        .updateConfig(null, null, new anchor.BN(150), null, new anchor.BN(150))
+        .accounts({
+          programState: programStatePubkey,
+          authority: payer.publicKey,
+        })
+        .rpc();
+      assert.fail("update_config should reject a refund delay within expiry");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidRefundDelay");
+    }
+
+    const tx = await program.methods
+      .updateConfig(
+        4500,
+        null,
+        new anchor.BN(150),
+        500, // 5% protocol fee
+        new anchor.BN(151)
+      )
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+    console.log(`[TX] update_config: ${tx}`);
+
+    const programState = await program.account.programState.fetch(
+      programStatePubkey
+    );
+    assert.equal(programState.lotteryDurationSlots, 4500);
+    assert.equal(programState.slotsUntilExpiration.toNumber(), 150);
+    assert.equal(programState.feeBps, 500);
+    assert.equal(programState.refundDelaySlots.toNumber(), 151);
+  });
+
+  it("set_function rejects signers other than the authority", async () => {
+    const attacker = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .setFunction()
+        .accounts({
+          programState: programStatePubkey,
+          authority: attacker.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+        })
+        .signers([attacker])
+        .rpc();
+      assert.fail("set_function should have required the authority");
+    } catch (error) {
+      assert.include(`${error}`, "ConstraintHasOne");
+    }
+  });
+
+  it("set_function", async () => {
+    const tx = await program.methods
+      .setFunction()
+      .accounts({
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+      })
+      .rpc();
+    console.log(`[TX] set_function: ${tx}`);
+
+    const programState = await program.account.programState.fetch(
+      programStatePubkey
+    );
+    assert.equal(
+      programState.switchboardFunction.toBase58(),
+      switchboardFunction.publicKey.toBase58()
+    );
+  });
+
+  it("propose_authority and accept_authority reject outsiders", async () => {
+    const attacker = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .proposeAuthority(attacker.publicKey)
+        .accounts({
+          programState: programStatePubkey,
+          authority: attacker.publicKey,
+        })
+        .signers([attacker])
+        .rpc();
+      assert.fail("propose_authority should have required the authority");
+    } catch (error) {
+      assert.include(`${error}`, "ConstraintHasOne");
+    }
+
+    // Only the proposed authority can accept
+    const newAuthority = anchor.web3.Keypair.generate();
+    await program.methods
+      .proposeAuthority(newAuthority.publicKey)
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+    try {
+      await program.methods
+        .acceptAuthority()
+        .accounts({
+          programState: programStatePubkey,
+          pendingAuthority: attacker.publicKey,
+        })
+        .signers([attacker])
+        .rpc();
+      assert.fail("accept_authority should have required the proposal");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidAuthority");
+    }
+
+    // Withdraw the proposal so it can't be accepted later
+    await program.methods
+      .proposeAuthority(anchor.web3.PublicKey.default)
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+  });
+
+  it("propose_authority and accept_authority", async () => {
+    const newAuthority = anchor.web3.Keypair.generate();
+
+    // Hand the program to a new authority then hand it back
+    for (const [current, next] of [
+      [payer, newAuthority],
+      [newAuthority, payer],
+    ]) {
+      await program.methods
+        .proposeAuthority(next.publicKey)
+        .accounts({
+          programState: programStatePubkey,
+          authority: current.publicKey,
+        })
+        .signers([current])
+        .rpc();
+      await program.methods
+        .acceptAuthority()
+        .accounts({
+          programState: programStatePubkey,
+          pendingAuthority: next.publicKey,
+        })
+        .signers([next])
+        .rpc();
+
+      const programState = await program.account.programState.fetch(
+        programStatePubkey
+      );
+      assert.equal(
+        programState.authority.toBase58(),
+        next.publicKey.toBase58()
+      );
+      assert.equal(
+        programState.pendingAuthority.toBase58(),
+        anchor.web3.PublicKey.default.toBase58()
+      );
+    }
+  });
+
+  it("initialize_treasury", async () => {
+    const tx = await program.methods
+      .initializeTreasury()
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        treasury: treasuryPubkey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_treasury: ${tx}`);
+  });
+
   ///////////////////////////////////////////////////////
   // Create our lottery account
   ///////////////////////////////////////////////////////
+  it("initialize_lottery_host", async () => {
+    const tx = await program.methods
+      .initializeLotteryHost()
+      .accounts({
+        payer: payer.publicKey,
+        lotteryHost: lotteryHostPubkey,
+        authority: payer.publicKey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_lottery_host: ${tx}`);
+  });
+
   it("create_lottery", async () => {
     console.log(`[INFO] lottery: ${lotteryPubkey.toBase58()}`);
     await sleep(3000);
     try {
       const tx = await program.methods
-        .createLottery(new anchor.BN(0), 10)
+        .createLottery(new anchor.BN(0), 10, false)
         .accounts({
           payer: payer.publicKey,
           programState: programStatePubkey,
+          lotteryHost: lotteryHostPubkey,
           lottery: lotteryPubkey,
           lotteryEscrow:
             switchboard.program.mint.getAssociatedAddress(lotteryPubkey),
@@ -112,6 +382,94 @@ describe("scheduled-lottery-request", () => {
       console.error(error);
       throw error;
     }
+
+    const lotteryHost = await program.account.lotteryHost.fetch(
+      lotteryHostPubkey
+    );
+    assert.equal(lotteryHost.nextLotteryId.toNumber(), 1);
+    const lotteryState = await program.account.lotteryState.fetch(
+      lotteryPubkey
+    );
+    assert.equal(lotteryState.lotteryId.toNumber(), 0);
+  });
+
+  it("create_lottery (recurring)", async () => {
+    const tx = await program.methods
+      .createLottery(
+        new anchor.BN(0.0001 * anchor.web3.LAMPORTS_PER_SOL),
+        10,
+        true
+      )
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        lotteryHost: lotteryHostPubkey,
+        lottery: recurringLotteryPubkey,
+        lotteryEscrow: switchboard.program.mint.getAssociatedAddress(
+          recurringLotteryPubkey
+        ),
+        authority: payer.publicKey,
+        switchboard: switchboard.program.attestationProgramId,
+        switchboardMint: switchboard.program.mint.address,
+        switchboardState: switchboard.program.attestationProgramState.publicKey,
+        switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: recurringRequestKeypair.publicKey,
+        switchboardRequestEscrow: switchboard.program.mint.getAssociatedAddress(
+          recurringRequestKeypair.publicKey
+        ),
+      })
+      .signers([recurringRequestKeypair])
+      .preInstructions([
+        anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+          units: 300_000,
+        }),
+      ])
+      .rpc();
+    console.log(`[TX] create_lottery (recurring): ${tx}`);
+
+    const lotteryState = await program.account.lotteryState.fetch(
+      recurringLotteryPubkey
+    );
+    assert(lotteryState.recurring);
+    assert.equal(lotteryState.durationSlots, 10);
+    assert.equal(lotteryState.round, 0);
+  });
+
+  it("pause rejects ticket purchases", async () => {
+    const PAUSE_BUY_TICKET = 1 << 1;
+    await program.methods
+      .pause(PAUSE_BUY_TICKET)
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+
+    const user = await createUser(
+      provider,
+      0.001 * anchor.web3.LAMPORTS_PER_SOL
+    );
+    try {
+      await program.methods
+        .buyTicket()
+        .accounts({
+          payer: user.publicKey,
+          programState: programStatePubkey,
+          lottery: lotteryPubkey,
+          escrow: anchor.utils.token.associatedAddress({
+            mint: switchboard.program.mint.address,
+            owner: lotteryPubkey,
+          }),
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("buy_ticket should have been paused");
+    } catch (error) {
+      assert.include(`${error}`, "ProgramPaused");
+    }
+
+    await program.methods
+      .unpause(PAUSE_BUY_TICKET)
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
   });
 
   it("buy_ticket", async () => {
@@ -123,6 +481,7 @@ describe("scheduled-lottery-request", () => {
       .buyTicket()
       .accounts({
         payer: user1.publicKey,
+        programState: programStatePubkey,
         lottery: lotteryPubkey,
         escrow: anchor.utils.token.associatedAddress({
           mint: switchboard.program.mint.address,
@@ -147,6 +506,8 @@ describe("scheduled-lottery-request", () => {
       lotteryPubkey
     );
     const closingSlot = initialLotteryState.closeSlot.toNumber();
+    // With a single ticket sold, any randomness selects the first ticket
+    const randomness = Array.from(randomBytes(32));
     const winner = initialLotteryState.tickets[0];
 
     let slot = await provider.connection.getSlot();
@@ -160,49 +521,13 @@ describe("scheduled-lottery-request", () => {
     // Normally this happens within the enclave
     const enclaveSigner = anchor.web3.Keypair.generate();
 
-    // Load the Switchboard account states
-    const [_sbRequestAccount, sbRequestState] =
-      await FunctionRequestAccount.load(
-        switchboard.program,
-        switchboardRequestKeypair.publicKey
-      );
-    const sbFunctionState = await switchboardFunction.loadData();
-
-    // We need a wrapped SOL TokenAccount to receive the oracle reward from the fn request escrow
-    const rewardAddress =
-      await switchboard.program.mint.getOrCreateAssociatedUser(payer.publicKey);
-
-    // Next, generate the function_request_verify ixn that we must call before running
-    // any of our emitted instructions.
-    const fnRequestVerifyIxn = attestationTypes.functionRequestVerify(
-      switchboard.program,
-      {
-        params: {
-          observedTime: new anchor.BN(Math.floor(Date.now() / 1000)),
-          errorCode: 0,
-          mrEnclave: Array.from(MRENCLAVE),
-          requestSlot: sbRequestState.activeRequest.requestSlot,
-          containerParamsHash: sbRequestState.containerParamsHash,
-        },
-      },
-      {
-        request: switchboardRequestKeypair.publicKey,
-        functionEnclaveSigner: enclaveSigner.publicKey,
-        escrow: sbRequestState.escrow,
-        function: switchboardFunction.publicKey,
-        functionEscrow: sbFunctionState.escrowTokenWallet,
-        verifierQuote: switchboard.verifier.publicKey,
-        verifierEnclaveSigner: switchboard.verifier.signer.publicKey,
-        verifierPermission: switchboard.verifier.permissionAccount.publicKey,
-        state: switchboard.program.attestationProgramState.publicKey,
-        attestationQueue: switchboard.attestationQueue.publicKey,
-        receiver: rewardAddress,
-        tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
-      }
+    const fnRequestVerifyIxn = await buildRequestVerifyIxn(
+      switchboardRequestKeypair.publicKey,
+      enclaveSigner
     );
 
     const tx = await program.methods
-      .drawWinner(winner)
+      .drawWinner(randomness)
       .accounts({
         lottery: lotteryPubkey,
         escrow: anchor.utils.token.associatedAddress({
@@ -219,6 +544,15 @@ describe("scheduled-lottery-request", () => {
           mint: switchboard.program.mint.address,
           owner: switchboardRequestKeypair.publicKey,
         }),
+        programState: programStatePubkey,
+        treasury: treasuryPubkey,
+        switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+        switchboardMint: switchboard.program.mint.address,
+        lotteryVault: anchor.web3.PublicKey.findProgramAddressSync(
+          [Buffer.from("LOTTERY_VAULT"), lotteryPubkey.toBytes()],
+          program.programId
+        )[0],
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
       })
       .preInstructions([fnRequestVerifyIxn])
       .signers([enclaveSigner, switchboard.verifier.signer])
@@ -230,6 +564,277 @@ describe("scheduled-lottery-request", () => {
     );
     assert(lotteryState.hasEnded);
     assert(lotteryState.winner.equals(winner));
+
+    const treasuryState = await program.account.treasury.fetch(treasuryPubkey);
+    assert.equal(
+      treasuryState.totalCollected.toNumber(),
+      initialLotteryState.entryFee.toNumber() *
+        initialLotteryState.numTickets *
+        0.05
+    );
+  });
+
+  it("draw_winner (recurring)", async () => {
+    // The next round's oracle bounty is topped up from the jackpot
+    const bounty = 1000;
+    await program.methods
+      .updateConfig(null, new anchor.BN(bounty), null, null, null)
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+
+    const escrow = switchboard.program.mint.getAssociatedAddress(
+      recurringLotteryPubkey
+    );
+    const requestEscrow = switchboard.program.mint.getAssociatedAddress(
+      recurringRequestKeypair.publicKey
+    );
+    const user = await createUser(
+      provider,
+      0.01 * anchor.web3.LAMPORTS_PER_SOL
+    );
+    await program.methods
+      .buyTicket()
+      .accounts({
+        payer: user.publicKey,
+        programState: programStatePubkey,
+        lottery: recurringLotteryPubkey,
+        escrow,
+      })
+      .signers([user])
+      .rpc();
+
+    const initialLotteryState = await program.account.lotteryState.fetch(
+      recurringLotteryPubkey
+    );
+    const closingSlot = initialLotteryState.closeSlot.toNumber();
+    while ((await provider.connection.getSlot()) < closingSlot) {
+      await sleep(1000);
+    }
+
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    const fnRequestVerifyIxn = await buildRequestVerifyIxn(
+      recurringRequestKeypair.publicKey,
+      enclaveSigner
+    );
+    const requestEscrowBalance = Number(
+      (await provider.connection.getTokenAccountBalance(requestEscrow)).value
+        .amount
+    );
+    const userBalance = await provider.connection.getBalance(user.publicKey);
+    const { totalCollected } = await program.account.treasury.fetch(
+      treasuryPubkey
+    );
+
+    const tx = await program.methods
+      .drawWinner(Array.from(randomBytes(32)))
+      .accounts({
+        lottery: recurringLotteryPubkey,
+        escrow,
+        winner: user.publicKey,
+        switchboard: switchboard.program.attestationProgramId,
+        switchboardState: switchboard.program.attestationProgramState.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: recurringRequestKeypair.publicKey,
+        enclaveSigner: enclaveSigner.publicKey,
+        switchboardRequestEscrow: requestEscrow,
+        programState: programStatePubkey,
+        treasury: treasuryPubkey,
+        switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+        switchboardMint: switchboard.program.mint.address,
+        lotteryVault: anchor.web3.PublicKey.findProgramAddressSync(
+          [Buffer.from("LOTTERY_VAULT"), recurringLotteryPubkey.toBytes()],
+          program.programId
+        )[0],
+        associatedTokenProgram: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+      })
+      .preInstructions([fnRequestVerifyIxn])
+      .signers([enclaveSigner, switchboard.verifier.signer])
+      .rpc({ skipPreflight: true });
+    console.log(`[TX] draw_winner (recurring): ${tx}`);
+
+    const lotteryState = await program.account.lotteryState.fetch(
+      recurringLotteryPubkey
+    );
+    assert(!lotteryState.hasEnded);
+    assert.equal(lotteryState.round, 1);
+    assert.equal(lotteryState.numTickets, 0);
+    assert(lotteryState.previousWinner.equals(user.publicKey));
+
+    // The request escrow is topped up to the bounty out of the jackpot, and the
+    // protocol fee and the winner's payout are taken from the rest.
+    const topUp = Math.max(bounty - requestEscrowBalance, 0);
+    const jackpot = initialLotteryState.entryFee.toNumber() - topUp;
+    const fee = Math.floor((jackpot * 500) / 10_000);
+    assert.equal(
+      Number(
+        (await provider.connection.getTokenAccountBalance(requestEscrow)).value
+          .amount
+      ),
+      requestEscrowBalance + topUp
+    );
+    assert.equal(
+      await provider.connection.getBalance(user.publicKey),
+      userBalance + jackpot - fee
+    );
+    const treasuryState = await program.account.treasury.fetch(treasuryPubkey);
+    assert.equal(
+      treasuryState.totalCollected.toNumber(),
+      totalCollected.toNumber() + fee
+    );
+  });
+
+  it("claim_refund", async () => {
+    const [refundLotteryPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("LOTTERY_STATE"),
+        payer.publicKey.toBytes(),
+        new anchor.BN(2).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const refundEscrow =
+      switchboard.program.mint.getAssociatedAddress(refundLotteryPubkey);
+    const requestKeypair = anchor.web3.Keypair.generate();
+    await program.methods
+      .createLottery(
+        new anchor.BN(0.0001 * anchor.web3.LAMPORTS_PER_SOL),
+        10,
+        false
+      )
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        lotteryHost: lotteryHostPubkey,
+        lottery: refundLotteryPubkey,
+        lotteryEscrow: refundEscrow,
+        authority: payer.publicKey,
+        switchboard: switchboard.program.attestationProgramId,
+        switchboardMint: switchboard.program.mint.address,
+        switchboardState: switchboard.program.attestationProgramState.publicKey,
+        switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: requestKeypair.publicKey,
+        switchboardRequestEscrow: switchboard.program.mint.getAssociatedAddress(
+          requestKeypair.publicKey
+        ),
+      })
+      .signers([requestKeypair])
+      .preInstructions([
+        anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+          units: 300_000,
+        }),
+      ])
+      .rpc();
+
+    const user = await createUser(
+      provider,
+      0.01 * anchor.web3.LAMPORTS_PER_SOL
+    );
+    await sleep(1000);
+    await program.methods
+      .buyTicket()
+      .accounts({
+        payer: user.publicKey,
+        programState: programStatePubkey,
+        lottery: refundLotteryPubkey,
+        escrow: refundEscrow,
+      })
+      .signers([user])
+      .rpc();
+
+    const claimRefund = () =>
+      program.methods
+        .claimRefund(0)
+        .accounts({
+          payer: user.publicKey,
+          programState: programStatePubkey,
+          lottery: refundLotteryPubkey,
+          escrow: refundEscrow,
+          authority: payer.publicKey,
+          lotteryVault: anchor.web3.PublicKey.findProgramAddressSync(
+            [Buffer.from("LOTTERY_VAULT"), refundLotteryPubkey.toBytes()],
+            program.programId
+          )[0],
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: requestKeypair.publicKey,
+          switchboardRequestEscrow:
+            switchboard.program.mint.getAssociatedAddress(
+              requestKeypair.publicKey
+            ),
+        })
+        .signers([user])
+        .rpc();
+
+    try {
+      await claimRefund();
+      assert.fail("claim_refund should wait for the refund delay");
+    } catch (error) {
+      assert.include(`${error}`, "RefundNotAvailable");
+    }
+
+    const { closeSlot } = await program.account.lotteryState.fetch(
+      refundLotteryPubkey
+    );
+    const { refundDelaySlots } = await program.account.programState.fetch(
+      programStatePubkey
+    );
+    const refundSlot = closeSlot.toNumber() + refundDelaySlots.toNumber();
+    while ((await provider.connection.getSlot()) < refundSlot) {
+      await sleep(1000);
+    }
+
+    const balance = await provider.connection.getBalance(user.publicKey);
+    const tx = await claimRefund();
+    console.log(`[TX] claim_refund: ${tx}`);
+
+    const lotteryState = await program.account.lotteryState.fetch(
+      refundLotteryPubkey
+    );
+    assert(lotteryState.hasEnded);
+    assert(lotteryState.refunding);
+    // The user pays the transaction fee
+    assert.equal(
+      await provider.connection.getBalance(user.publicKey),
+      balance + lotteryState.entryFee.toNumber() - 5000
+    );
+    // The first refund closes the request and returns everything but the
+    // lottery's rent to the authority, so the lottery can't be drawn
+    assert.isNull(
+      await provider.connection.getAccountInfo(requestKeypair.publicKey)
+    );
+    const lotteryAccount = await provider.connection.getAccountInfo(
+      refundLotteryPubkey
+    );
+    assert.equal(
+      lotteryAccount.lamports,
+      await provider.connection.getMinimumBalanceForRentExemption(
+        lotteryAccount.data.length
+      )
+    );
+
+    try {
+      await claimRefund();
+      assert.fail("claim_refund should only refund a ticket once");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidTicket");
+    }
+  });
+
+  it("withdraw_fees", async () => {
+    const treasuryState = await program.account.treasury.fetch(treasuryPubkey);
+    const tx = await program.methods
+      .withdrawFees(treasuryState.totalCollected)
+      .accounts({
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        treasury: treasuryPubkey,
+        destination: payer.publicKey,
+      })
+      .rpc();
+    console.log(`[TX] withdraw_fees: ${tx}`);
   });
 });
 
@@ -10,7 +10,8 @@ import {
   attestationTypes,
 } from "@switchboard-xyz/solana.js";
 import { parseRawMrEnclave } from "@switchboard-xyz/common";
-import { loadSwitchboard } from "./utils";
+import { assert } from "chai";
+import { getProgramDataAddress, loadSwitchboard } from "./utils";
 
 // This value doesnt matter for our tests because we are not validating
 // the execution off-chain.
@@ -36,12 +37,20 @@ describe("super-simple-randomness", () => {
     program.programId
   );
 
+  // Derive the house vault which escrows wagers and pays out winners.
+  const [housePubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("HOUSE_VAULT")],
+    program.programId
+  );
+
   ///////////////////////////////////////////////////////
   // Switchboard setup
   ///////////////////////////////////////////////////////
   let switchboard: BootstrappedAttestationQueue;
   let switchboardFunction: FunctionAccount;
   const switchboardRequestKeypair = anchor.web3.Keypair.generate();
+  // The request created by the users second guess, once the first has settled
+  const nextRequestKeypair = anchor.web3.Keypair.generate();
 
   before(async () => {
     [switchboard, switchboardFunction] = await loadSwitchboard(
@@ -51,14 +60,141 @@ describe("super-simple-randomness", () => {
     );
   });
 
+  ///////////////////////////////////////////////////////
+  // Initialize and fund the house vault
+  ///////////////////////////////////////////////////////
+  it("initialize_house rejects non upgrade authority signers", async () => {
+    const attacker = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .initializeHouse(100, 5000)
+        .accounts({
+          payer: payer.publicKey,
+          house: housePubkey,
+          authority: attacker.publicKey,
+          program: program.programId,
+          programData: getProgramDataAddress(program.programId),
+        })
+        .signers([attacker])
+        .rpc();
+      assert.fail(
+        "initialize_house should have required the upgrade authority"
+      );
+    } catch (error) {
+      assert.include(`${error}`, "InvalidAuthority");
+    }
+  });
+
+  it("initialize_house", async () => {
+    const tx = await program.methods
+      .initializeHouse(100, 5000) // 1% house edge, 50% max exposure
+      .accounts({
+        payer: payer.publicKey,
+        house: housePubkey,
+        authority: payer.publicKey,
+        program: program.programId,
+        programData: getProgramDataAddress(program.programId),
+      })
+      .postInstructions([
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: payer.publicKey,
+          toPubkey: housePubkey,
+          lamports: anchor.web3.LAMPORTS_PER_SOL,
+        }),
+      ])
+      .rpc();
+    console.log(`[TX] initialize_house: ${tx}`);
+  });
+
+  ///////////////////////////////////////////////////////
+  // Configure the result bounds
+  ///////////////////////////////////////////////////////
+  it("set_result_bounds rejects invalid bounds", async () => {
+    for (const [minResult, maxResult] of [
+      [5, 4], // min > max
+      [0, 0], // empty upper bound
+      [1, 4294967295], // above u32::MAX - 1
+    ]) {
+      try {
+        await program.methods
+          .setResultBounds(minResult, maxResult)
+          .accounts({ house: housePubkey, authority: payer.publicKey })
+          .rpc();
+        assert.fail(`set_result_bounds accepted [${minResult}, ${maxResult}]`);
+      } catch (error) {
+        assert.include(`${error}`, "InvalidResultRange");
+      }
+    }
+
+    const attacker = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .setResultBounds(1, 100)
+        .accounts({ house: housePubkey, authority: attacker.publicKey })
+        .signers([attacker])
+        .rpc();
+      assert.fail("set_result_bounds should have required the house authority");
+    } catch (error) {
+      assert.include(`${error}`, "ConstraintHasOne");
+    }
+  });
+
+  it("set_result_bounds", async () => {
+    await program.methods
+      .setResultBounds(2, 5)
+      .accounts({ house: housePubkey, authority: payer.publicKey })
+      .rpc();
+    let houseState = await program.account.houseState.fetch(housePubkey);
+    assert.equal(houseState.minResult, 2);
+    assert.equal(houseState.maxResult, 5);
+
+    // Guesses must now request a result range within [2, 5]
+    const requestKeypair = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .guess(1, new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL), 1, 10)
+        .accounts({
+          payer: payer.publicKey,
+          house: housePubkey,
+          user: userPubkey,
+          authority: payer.publicKey,
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: requestKeypair.publicKey,
+          switchboardRequestEscrow: anchor.utils.token.associatedAddress({
+            mint: switchboard.program.mint.address,
+            owner: requestKeypair.publicKey,
+          }),
+          switchboardMint: switchboard.program.mint.address,
+        })
+        .signers([requestKeypair])
+        .rpc();
+      assert.fail("guess should have rejected a range outside the bounds");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidResultRange");
+    }
+
+    await program.methods
+      .setResultBounds(1, 10)
+      .accounts({ house: housePubkey, authority: payer.publicKey })
+      .rpc();
+    houseState = await program.account.houseState.fetch(housePubkey);
+    assert.equal(houseState.minResult, 1);
+    assert.equal(houseState.maxResult, 10);
+  });
+
   ///////////////////////////////////////////////////////
   // Submit a guess
   ///////////////////////////////////////////////////////
   it("guess", async () => {
     const tx = await program.methods
-      .guess(1)
+      .guess(1, new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL), 1, 10)
       .accounts({
         payer: payer.publicKey,
+        house: housePubkey,
         user: userPubkey,
         authority: payer.publicKey,
         switchboard: switchboard.program.attestationProgramId,
@@ -78,28 +214,177 @@ describe("super-simple-randomness", () => {
   });
 
   ///////////////////////////////////////////////////////
-  // Mock off-chain settle logic
+  // Submit multiple guesses in parallel with nonce-seeded tickets
   ///////////////////////////////////////////////////////
-  it("settle", async () => {
-    // First, generate a new keypair to sign our instruction
-    // Normally this happens within the enclave
-    const enclaveSigner = anchor.web3.Keypair.generate();
+  it("guess_with_ticket", async () => {
+    const nonces = [new anchor.BN(0), new anchor.BN(1)];
+    const ticketPubkeys = nonces.map(
+      (nonce) =>
+        anchor.web3.PublicKey.findProgramAddressSync(
+          [
+            Buffer.from("RANDOMNESS_USER"),
+            payer.publicKey.toBytes(),
+            nonce.toArrayLike(Buffer, "le", 8),
+          ],
+          program.programId
+        )[0]
+    );
 
+    await Promise.all(
+      nonces.map(async (nonce, i) => {
+        const requestKeypair = anchor.web3.Keypair.generate();
+        const tx = await program.methods
+          .guessWithTicket(nonce, i + 1, new anchor.BN(0), 1, 10)
+          .accounts({
+            payer: payer.publicKey,
+            house: housePubkey,
+            ticket: ticketPubkeys[i],
+            authority: payer.publicKey,
+            switchboard: switchboard.program.attestationProgramId,
+            switchboardState:
+              switchboard.program.attestationProgramState.publicKey,
+            switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+            switchboardFunction: switchboardFunction.publicKey,
+            switchboardRequest: requestKeypair.publicKey,
+            switchboardRequestEscrow: anchor.utils.token.associatedAddress({
+              mint: switchboard.program.mint.address,
+              owner: requestKeypair.publicKey,
+            }),
+            switchboardMint: switchboard.program.mint.address,
+          })
+          .signers([requestKeypair])
+          .rpc();
+        console.log(`[TX] guess_with_ticket (nonce ${nonce}): ${tx}`);
+      })
+    );
+
+    const tickets = await program.account.guessTicket.fetchMultiple(
+      ticketPubkeys
+    );
+    tickets.forEach((ticket, i) => {
+      assert.equal(ticket.nonce.toNumber(), i);
+      assert.equal(ticket.guess, i + 1);
+    });
+
+    // An unsettled ticket can't be cancelled while its request can still settle
+    try {
+      await program.methods
+        .cancelTicket()
+        .accounts({
+          ticket: ticketPubkeys[0],
+          authority: payer.publicKey,
+          house: housePubkey,
+          switchboardRequest: tickets[0].switchboardRequest,
+        })
+        .rpc();
+      assert.fail("cancel_ticket should have rejected the live request");
+    } catch (error) {
+      assert.include(`${error}`, "RequestNotExpired");
+    }
+  });
+
+  ///////////////////////////////////////////////////////
+  // Relay a guess signed off-chain by another authority
+  ///////////////////////////////////////////////////////
+  it("guess_relayed", async () => {
+    const relayedAuthority = anchor.web3.Keypair.generate();
+    const [relayedUserPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("RANDOMNESS_USER"), relayedAuthority.publicKey.toBytes()],
+      program.programId
+    );
+    const [relayPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("RELAY_NONCE"), relayedAuthority.publicKey.toBytes()],
+      program.programId
+    );
+    const wager = new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL);
+    const message = relayedGuessMessage(
+      program.programId,
+      relayedAuthority.publicKey,
+      0,
+      3,
+      wager,
+      1,
+      10
+    );
+
+    const relayGuess = (nonce: number, requestKeypair: anchor.web3.Keypair) =>
+      program.methods
+        .guessRelayed(nonce, 3, wager, 1, 10)
+        .accounts({
+          guess: {
+            payer: payer.publicKey,
+            house: housePubkey,
+            user: relayedUserPubkey,
+            authority: relayedAuthority.publicKey,
+            switchboard: switchboard.program.attestationProgramId,
+            switchboardState:
+              switchboard.program.attestationProgramState.publicKey,
+            switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+            switchboardFunction: switchboardFunction.publicKey,
+            switchboardRequest: requestKeypair.publicKey,
+            switchboardRequestEscrow: anchor.utils.token.associatedAddress({
+              mint: switchboard.program.mint.address,
+              owner: requestKeypair.publicKey,
+            }),
+            switchboardMint: switchboard.program.mint.address,
+          },
+          relay: relayPubkey,
+          instructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .preInstructions([
+          anchor.web3.Ed25519Program.createInstructionWithPrivateKey({
+            privateKey: relayedAuthority.secretKey,
+            message,
+          }),
+        ])
+        .signers([requestKeypair]);
+
+    // The signed message is bound to nonce 0
+    try {
+      await relayGuess(1, anchor.web3.Keypair.generate()).rpc();
+      assert.fail("guess_relayed should have rejected the signature");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidRelaySignature");
+    }
+
+    const tx = await relayGuess(0, anchor.web3.Keypair.generate()).rpc();
+    console.log(`[TX] guess_relayed: ${tx}`);
+
+    const userState = await program.account.userState.fetch(relayedUserPubkey);
+    assert(userState.authority.equals(relayedAuthority.publicKey));
+    assert.equal(userState.guess, 3);
+
+    const relayState = await program.account.relayState.fetch(relayPubkey);
+    assert.equal(relayState.nonce, 1);
+
+    // The same signed guess can't be relayed twice
+    try {
+      await relayGuess(0, anchor.web3.Keypair.generate()).rpc();
+      assert.fail("guess_relayed should have rejected the replayed nonce");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidRelayNonce");
+    }
+  });
+
+  ///////////////////////////////////////////////////////
+  // Mock off-chain settle logic
+  ///////////////////////////////////////////////////////
+  // Generates the function_request_verify ixn that must run before any of our emitted
+  // instructions. Normally this happens within the enclave.
+  async function buildRequestVerifyIxn(
+    requestPubkey: anchor.web3.PublicKey,
+    enclaveSigner: anchor.web3.Keypair
+  ): Promise<anchor.web3.TransactionInstruction> {
     // Load the Switchboard account states
     const [_sbRequestAccount, sbRequestState] =
-      await FunctionRequestAccount.load(
-        switchboard.program,
-        switchboardRequestKeypair.publicKey
-      );
+      await FunctionRequestAccount.load(switchboard.program, requestPubkey);
     const sbFunctionState = await switchboardFunction.loadData();
 
     // We need a wrapped SOL TokenAccount to receive the oracle reward from the fn request escrow
     const rewardAddress =
       await switchboard.program.mint.getOrCreateAssociatedUser(payer.publicKey);
 
-    // Next, generate the function_request_verify ixn that we must call before running
-    // any of our emitted instructions.
-    const fnRequestVerifyIxn = attestationTypes.functionRequestVerify(
+    return attestationTypes.functionRequestVerify(
       switchboard.program,
       {
         params: {
@@ -111,7 +396,7 @@ describe("super-simple-randomness", () => {
         },
       },
       {
-        request: switchboardRequestKeypair.publicKey,
+        request: requestPubkey,
         functionEnclaveSigner: enclaveSigner.publicKey,
         escrow: sbRequestState.escrow,
         function: switchboardFunction.publicKey,
@@ -125,14 +410,27 @@ describe("super-simple-randomness", () => {
         tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
       }
     );
+  }
 
+  it("settle", async () => {
+    // First, generate a new keypair to sign our instruction
+    // Normally this happens within the enclave
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    const fnRequestVerifyIxn = await buildRequestVerifyIxn(
+      switchboardRequestKeypair.publicKey,
+      enclaveSigner
+    );
+
+    // The highest result in the guess' [1, 10] range must settle
     const tx = await program.methods
-      .settle(1)
+      .settle(10)
       .accounts({
         user: userPubkey,
         switchboardFunction: switchboardFunction.publicKey,
         switchboardRequest: switchboardRequestKeypair.publicKey,
         enclaveSigner: enclaveSigner.publicKey,
+        house: housePubkey,
+        authority: payer.publicKey,
       })
       .preInstructions([fnRequestVerifyIxn])
       .signers([enclaveSigner, switchboard.verifier.signer])
@@ -140,10 +438,206 @@ describe("super-simple-randomness", () => {
     console.log(`[TX] settle: ${tx}`);
 
     const userState = await program.account.userState.fetch(userPubkey);
+    assert.equal(userState.result, 10);
     if (userState.guess === userState.result) {
       console.log(`[RESULT] user won!`);
     } else {
       console.log(`[RESULT] user lost :(`);
     }
   });
+
+  it("settle_ticket", async () => {
+    const [ticketPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("RANDOMNESS_USER"),
+        payer.publicKey.toBytes(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const ticket = await program.account.guessTicket.fetch(ticketPubkey);
+
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    const fnRequestVerifyIxn = await buildRequestVerifyIxn(
+      ticket.switchboardRequest,
+      enclaveSigner
+    );
+
+    // The lowest result in the ticket's [1, 10] range must settle
+    const tx = await program.methods
+      .settleTicket(1)
+      .accounts({
+        ticket: ticketPubkey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: ticket.switchboardRequest,
+        enclaveSigner: enclaveSigner.publicKey,
+        house: housePubkey,
+        authority: payer.publicKey,
+      })
+      .preInstructions([fnRequestVerifyIxn])
+      .signers([enclaveSigner, switchboard.verifier.signer])
+      .rpc();
+    console.log(`[TX] settle_ticket: ${tx}`);
+
+    const ticketState = await program.account.guessTicket.fetch(ticketPubkey);
+    assert.equal(ticketState.result, 1);
+    assert.isAbove(ticketState.settledTimestamp.toNumber(), 0);
+  });
+
+  ///////////////////////////////////////////////////////
+  // Reclaim rent from the settled request and user accounts
+  ///////////////////////////////////////////////////////
+  it("close_request", async () => {
+    const escrowDest =
+      await switchboard.program.mint.getOrCreateAssociatedUser(payer.publicKey);
+    const closeRequest = (requestPubkey: anchor.web3.PublicKey) =>
+      program.methods
+        .closeRequest()
+        .accounts({
+          user: userPubkey,
+          authority: payer.publicKey,
+          escrowDest,
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: requestPubkey,
+          switchboardRequestEscrow: anchor.utils.token.associatedAddress({
+            mint: switchboard.program.mint.address,
+            owner: requestPubkey,
+          }),
+        })
+        .rpc();
+
+    // Guessing again leaves the first, settled request behind
+    const slot = await provider.connection.getSlot();
+    await program.methods
+      .guess(2, new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL), 1, 10)
+      .accounts({
+        payer: payer.publicKey,
+        house: housePubkey,
+        user: userPubkey,
+        authority: payer.publicKey,
+        switchboard: switchboard.program.attestationProgramId,
+        switchboardState: switchboard.program.attestationProgramState.publicKey,
+        switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: nextRequestKeypair.publicKey,
+        switchboardRequestEscrow: anchor.utils.token.associatedAddress({
+          mint: switchboard.program.mint.address,
+          owner: nextRequestKeypair.publicKey,
+        }),
+        switchboardMint: switchboard.program.mint.address,
+      })
+      .signers([nextRequestKeypair])
+      .rpc();
+
+    // Anyone can close the new request once it is ~ 1 hour old
+    const [_sbRequestAccount, sbRequestState] =
+      await FunctionRequestAccount.load(
+        switchboard.program,
+        nextRequestKeypair.publicKey
+      );
+    assert.isAtLeast(
+      sbRequestState.garbageCollectionSlot.toNumber(),
+      slot + 9000
+    );
+
+    // The current request can't be closed while its guess is pending
+    try {
+      await closeRequest(nextRequestKeypair.publicKey);
+      assert.fail("close_request should have rejected the pending request");
+    } catch (error) {
+      assert.include(`${error}`, "RequestNotReady");
+    }
+
+    const tx = await closeRequest(switchboardRequestKeypair.publicKey);
+    console.log(`[TX] close_request: ${tx}`);
+    assert.isNull(
+      await provider.connection.getAccountInfo(
+        switchboardRequestKeypair.publicKey
+      )
+    );
+
+    // Once settled, the current request can be closed ahead of the user
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    await program.methods
+      .settle(2)
+      .accounts({
+        user: userPubkey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: nextRequestKeypair.publicKey,
+        enclaveSigner: enclaveSigner.publicKey,
+        house: housePubkey,
+        authority: payer.publicKey,
+      })
+      .preInstructions([
+        await buildRequestVerifyIxn(
+          nextRequestKeypair.publicKey,
+          enclaveSigner
+        ),
+      ])
+      .signers([enclaveSigner, switchboard.verifier.signer])
+      .rpc();
+    await closeRequest(nextRequestKeypair.publicKey);
+    assert.isNull(
+      await provider.connection.getAccountInfo(nextRequestKeypair.publicKey)
+    );
+  });
+
+  it("close", async () => {
+    const escrowDest =
+      await switchboard.program.mint.getOrCreateAssociatedUser(payer.publicKey);
+
+    const tx = await program.methods
+      .close()
+      .accounts({
+        user: userPubkey,
+        authority: payer.publicKey,
+        escrowDest,
+        house: housePubkey,
+        switchboard: switchboard.program.attestationProgramId,
+        switchboardState: switchboard.program.attestationProgramState.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+        // The current request was already closed by close_request
+        switchboardRequest: nextRequestKeypair.publicKey,
+        switchboardRequestEscrow: anchor.utils.token.associatedAddress({
+          mint: switchboard.program.mint.address,
+          owner: nextRequestKeypair.publicKey,
+        }),
+        switchboardMint: switchboard.program.mint.address,
+      })
+      .rpc();
+    console.log(`[TX] close: ${tx}`);
+
+    const userAccount = await provider.connection.getAccountInfo(userPubkey);
+    assert.isNull(userAccount);
+  });
 });
+
+// Mirrors relayed_guess_message in the program.
+function relayedGuessMessage(
+  programId: anchor.web3.PublicKey,
+  authority: anchor.web3.PublicKey,
+  nonce: number,
+  guess: number,
+  wager: anchor.BN,
+  minResult: number,
+  maxResult: number
+): Buffer {
+  const u32 = (value: number) => {
+    const buffer = Buffer.alloc(4);
+    buffer.writeUInt32LE(value);
+    return buffer;
+  };
+  return Buffer.concat([
+    Buffer.from("SIMPLE_RANDOMNESS_RELAYED_GUESS"),
+    programId.toBuffer(),
+    authority.toBuffer(),
+    u32(nonce),
+    u32(guess),
+    wager.toArrayLike(Buffer, "le", 8),
+    u32(minResult),
+    u32(maxResult),
+  ]);
+}
@@ -11,7 +11,11 @@ import {
 } from "@switchboard-xyz/solana.js";
 import { parseRawMrEnclave, sleep } from "@switchboard-xyz/common";
 import { assert } from "chai";
-import { loadSwitchboard } from "./utils";
+import {
+  createAssociatedTokenAccountIxn,
+  getProgramDataAddress,
+  loadSwitchboard,
+} from "./utils";
 
 // This value doesnt matter for our tests because we are not validating
 // the execution off-chain.
@@ -40,6 +44,27 @@ describe("switchboard-randomness-callback", () => {
     [Buffer.from("RANDOMNESS_USER"), payer.publicKey.toBytes()],
     program.programId
   );
+  const [housePubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("HOUSE_VAULT")],
+    program.programId
+  );
+  const [shareMintPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("HOUSE_SHARE_MINT")],
+    program.programId
+  );
+  const [statsPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("SIMPLE_RANDOMNESS"), Buffer.from("GLOBAL_STATS")],
+    program.programId
+  );
+  const [treasuryPubkey] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("SIMPLE_RANDOMNESS"), Buffer.from("TREASURY")],
+    program.programId
+  );
+  const providerSharesPubkey = anchor.utils.token.associatedAddress({
+    mint: shareMintPubkey,
+    owner: payer.publicKey,
+  });
+  const sessionKeypair = anchor.web3.Keypair.generate();
 
   ///////////////////////////////////////////////////////
   // Switchboard setup
@@ -59,6 +84,27 @@ describe("switchboard-randomness-callback", () => {
   ///////////////////////////////////////////////////////
   // Initialize the program and set the Switchboard Function
   ///////////////////////////////////////////////////////
+  it("initialize rejects signers other than the upgrade authority", async () => {
+    const attacker = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .initialize()
+        .accounts({
+          payer: payer.publicKey,
+          programState: programStatePubkey,
+          authority: attacker.publicKey,
+          program: program.programId,
+          programData: getProgramDataAddress(program.programId),
+          switchboardFunction: switchboardFunction.publicKey,
+        })
+        .signers([attacker])
+        .rpc();
+      assert.fail("initialize should have required the upgrade authority");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidAuthority");
+    }
+  });
+
   it("initialize", async () => {
     const tx = await program.methods
       .initialize()
@@ -66,12 +112,192 @@ describe("switchboard-randomness-callback", () => {
         payer: payer.publicKey,
         programState: programStatePubkey,
         authority: payer.publicKey,
+        program: program.programId,
+        programData: getProgramDataAddress(program.programId),
         switchboardFunction: switchboardFunction.publicKey,
       })
       .rpc();
     console.log(`[TX] initialize: ${tx}`);
   });
 
+  it("update_config", async () => {
+    try {
+      await program.methods
+        .updateConfig(new anchor.BN(1), null, null, null, null, null, null)
+        .accounts({
+          programState: programStatePubkey,
+          authority: payer.publicKey,
+        })
+        .rpc();
+      assert.fail("update_config should have rejected the request timeout");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidRequestTimeout");
+    }
+
+    // 300 slots is ~ 120 seconds, so a 60 second timeout would let a guess be cancelled early
+    try {
+      await program.methods
+        .updateConfig(
+          new anchor.BN(60),
+          null,
+          null,
+          null,
+          new anchor.BN(300),
+          null,
+          null
+        )
+        .accounts({
+          programState: programStatePubkey,
+          authority: payer.publicKey,
+        })
+        .rpc();
+      assert.fail("update_config should have rejected the request timeout");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidRequestTimeout");
+    }
+
+    const tx = await program.methods
+      .updateConfig(
+        new anchor.BN(120),
+        1,
+        10,
+        null,
+        new anchor.BN(300),
+        new anchor.BN(0.002 * anchor.web3.LAMPORTS_PER_SOL),
+        250 // 2.5% protocol fee
+      )
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+    console.log(`[TX] update_config: ${tx}`);
+
+    const programState = await program.account.programState.fetch(
+      programStatePubkey
+    );
+    assert.equal(programState.requestTimeout.toNumber(), 120);
+    assert.equal(programState.slotsUntilExpiration.toNumber(), 300);
+    assert.equal(
+      programState.minRequestEscrow.toNumber(),
+      0.002 * anchor.web3.LAMPORTS_PER_SOL
+    );
+    assert.equal(programState.feeBps, 250);
+  });
+
+  it("set_function", async () => {
+    const tx = await program.methods
+      .setFunction()
+      .accounts({
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+      })
+      .rpc();
+    console.log(`[TX] set_function: ${tx}`);
+  });
+
+  it("propose_authority and accept_authority", async () => {
+    const newAuthority = anchor.web3.Keypair.generate();
+
+    // Hand the program to a new authority then hand it back
+    for (const [current, next] of [
+      [payer, newAuthority],
+      [newAuthority, payer],
+    ]) {
+      await program.methods
+        .proposeAuthority(next.publicKey)
+        .accounts({
+          programState: programStatePubkey,
+          authority: current.publicKey,
+        })
+        .signers([current])
+        .rpc();
+      await program.methods
+        .acceptAuthority()
+        .accounts({
+          programState: programStatePubkey,
+          pendingAuthority: next.publicKey,
+        })
+        .signers([next])
+        .rpc();
+
+      const programState = await program.account.programState.fetch(
+        programStatePubkey
+      );
+      assert.equal(
+        programState.authority.toBase58(),
+        next.publicKey.toBase58()
+      );
+    }
+  });
+
+  it("initialize_stats", async () => {
+    const tx = await program.methods
+      .initializeStats()
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        stats: statsPubkey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_stats: ${tx}`);
+  });
+
+  it("initialize_treasury", async () => {
+    const tx = await program.methods
+      .initializeTreasury()
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        treasury: treasuryPubkey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_treasury: ${tx}`);
+  });
+
+  ///////////////////////////////////////////////////////
+  // Bankroll the house vault
+  ///////////////////////////////////////////////////////
+  it("initialize_house", async () => {
+    const tx = await program.methods
+      .initializeHouse(100, 5000) // 1% house edge, 50% max exposure
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        house: housePubkey,
+        shareMint: shareMintPubkey,
+      })
+      .rpc();
+    console.log(`[TX] initialize_house: ${tx}`);
+  });
+
+  it("deposit_liquidity", async () => {
+    const amount = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL);
+    const tx = await program.methods
+      .depositLiquidity(amount)
+      .accounts({
+        provider: payer.publicKey,
+        house: housePubkey,
+        shareMint: shareMintPubkey,
+        providerShares: providerSharesPubkey,
+      })
+      .preInstructions([
+        createAssociatedTokenAccountIxn(
+          payer.publicKey,
+          payer.publicKey,
+          shareMintPubkey
+        ),
+      ])
+      .rpc();
+    console.log(`[TX] deposit_liquidity: ${tx}`);
+
+    const shares = await provider.connection.getTokenAccountBalance(
+      providerSharesPubkey
+    );
+    assert.equal(shares.value.amount, amount.toString());
+  });
+
   it("create_user", async () => {
     const tx = await program.methods
       .createUser()
@@ -80,6 +306,7 @@ describe("switchboard-randomness-callback", () => {
         programState: programStatePubkey,
         user: userPubkey,
         authority: payer.publicKey,
+        stats: statsPubkey,
         switchboard: switchboard.program.attestationProgramId,
         switchboardMint: switchboard.program.mint.address,
         switchboardState: switchboard.program.attestationProgramState.publicKey,
@@ -95,13 +322,257 @@ describe("switchboard-randomness-callback", () => {
     console.log(`[TX] create_user: ${tx}`);
   });
 
+  it("migrate_user", async () => {
+    // Loaded at genesis from tests/fixtures/baseline-user.json with the
+    // original UserState layout: guess 3, result 7, settled a minute in
+    const baselineAuthority = new anchor.web3.PublicKey(
+      "cGfHiC6Kgg3FpFZvgwGcswsCRtp4aBP2fzuXRQPizuN"
+    );
+    const [baselineUserPubkey, baselineUserBump] =
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("RANDOMNESS_USER"), baselineAuthority.toBytes()],
+        program.programId
+      );
+    const before = await provider.connection.getAccountInfo(baselineUserPubkey);
+    assert.isNotNull(before);
+
+    const tx = await program.methods
+      .migrateUser()
+      .accounts({
+        payer: payer.publicKey,
+        user: baselineUserPubkey,
+        authority: baselineAuthority,
+      })
+      .rpc();
+    console.log(`[TX] migrate_user: ${tx}`);
+
+    const after = await provider.connection.getAccountInfo(baselineUserPubkey);
+    assert.isAbove(after.data.length, before.data.length);
+    assert.equal(
+      after.lamports,
+      await provider.connection.getMinimumBalanceForRentExemption(
+        after.data.length
+      )
+    );
+
+    const userState = await program.account.userState.fetch(
+      baselineUserPubkey
+    );
+    assert.equal(userState.bump, baselineUserBump);
+    assert.equal(userState.authority.toBase58(), baselineAuthority.toBase58());
+    assert.equal(
+      userState.switchboardRequest.toBase58(),
+      anchor.web3.PublicKey.default.toBase58()
+    );
+    assert.equal(userState.guess, 3);
+    assert.equal(userState.result, 7);
+    assert.equal(userState.minResult, 1);
+    assert.equal(userState.maxResult, 10);
+    assert.equal(userState.requestTimestamp.toNumber(), 1_700_000_000);
+    assert.equal(userState.settledTimestamp.toNumber(), 1_700_000_060);
+    assert.equal(userState.historyLen, 0);
+
+    // Migrating an account that already has the current layout is a no-op
+    await program.methods
+      .migrateUser()
+      .accounts({
+        payer: payer.publicKey,
+        user: baselineUserPubkey,
+        authority: baselineAuthority,
+      })
+      .rpc();
+    const again = await provider.connection.getAccountInfo(baselineUserPubkey);
+    assert.isTrue(again.data.equals(after.data));
+  });
+
+  it("guess rejects wagers above the exposure limit", async () => {
+    try {
+      await program.methods
+        .guess(
+          1,
+          new anchor.BN(0.1 * anchor.web3.LAMPORTS_PER_SOL),
+          1,
+          10,
+          null
+        )
+        .accounts({
+          payer: payer.publicKey,
+          programState: programStatePubkey,
+          user: userPubkey,
+          authority: payer.publicKey,
+          signer: payer.publicKey,
+          house: housePubkey,
+          stats: statsPubkey,
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: switchboardRequestKeypair.publicKey,
+          switchboardRequestEscrow:
+            switchboard.program.mint.getAssociatedAddress(
+              switchboardRequestKeypair.publicKey
+            ),
+        })
+        .rpc();
+      assert.fail("guess should have exceeded the exposure limit");
+    } catch (error) {
+      assert.include(`${error}`, "ExposureLimitExceeded");
+    }
+  });
+
+  it("pause rejects new guesses", async () => {
+    await program.methods
+      .pause(null)
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+
+    try {
+      await program.methods
+        .guess(
+          1,
+          new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL),
+          1,
+          10,
+          null
+        )
+        .accounts({
+          payer: payer.publicKey,
+          programState: programStatePubkey,
+          user: userPubkey,
+          authority: payer.publicKey,
+          signer: payer.publicKey,
+          house: housePubkey,
+          stats: statsPubkey,
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: switchboardRequestKeypair.publicKey,
+          switchboardRequestEscrow:
+            switchboard.program.mint.getAssociatedAddress(
+              switchboardRequestKeypair.publicKey
+            ),
+        })
+        .rpc();
+      assert.fail("guess should have been paused");
+    } catch (error) {
+      assert.include(`${error}`, "ProgramPaused");
+    }
+
+    await program.methods
+      .unpause(null)
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+  });
+
+  it("create_session", async () => {
+    const tx = await program.methods
+      .createSession(
+        sessionKeypair.publicKey,
+        new anchor.BN(3600),
+        new anchor.BN(0.001 * anchor.web3.LAMPORTS_PER_SOL)
+      )
+      .accounts({ user: userPubkey, authority: payer.publicKey })
+      .rpc();
+    console.log(`[TX] create_session: ${tx}`);
+
+    const userState = await program.account.userState.fetch(userPubkey);
+    assert(userState.sessionKey.equals(sessionKeypair.publicKey));
+    assert.equal(
+      userState.sessionAllowance.toNumber(),
+      0.001 * anchor.web3.LAMPORTS_PER_SOL
+    );
+  });
+
+  it("guess rejects session wagers above the allowance", async () => {
+    try {
+      await program.methods
+        .guess(
+          1,
+          new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL),
+          1,
+          10,
+          null
+        )
+        .accounts({
+          payer: payer.publicKey,
+          programState: programStatePubkey,
+          user: userPubkey,
+          authority: payer.publicKey,
+          signer: sessionKeypair.publicKey,
+          house: housePubkey,
+          stats: statsPubkey,
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: switchboardRequestKeypair.publicKey,
+          switchboardRequestEscrow:
+            switchboard.program.mint.getAssociatedAddress(
+              switchboardRequestKeypair.publicKey
+            ),
+        })
+        .signers([sessionKeypair])
+        .rpc();
+      assert.fail("guess should have exceeded the session allowance");
+    } catch (error) {
+      assert.include(`${error}`, "SessionAllowanceExceeded");
+    }
+  });
+
+  it("revoke_session", async () => {
+    const tx = await program.methods
+      .revokeSession()
+      .accounts({ user: userPubkey, authority: payer.publicKey })
+      .rpc();
+    console.log(`[TX] revoke_session: ${tx}`);
+
+    const userState = await program.account.userState.fetch(userPubkey);
+    assert(userState.sessionKey.equals(anchor.web3.PublicKey.default));
+  });
+
+  it("fund_request_escrow", async () => {
+    const escrowPubkey = switchboard.program.mint.getAssociatedAddress(
+      switchboardRequestKeypair.publicKey
+    );
+    const initialBalance = await program.provider.connection
+      .getTokenAccountBalance(escrowPubkey)
+      .then((balance) => Number(balance.value.amount));
+
+    const tx = await program.methods
+      .fundRequestEscrow(new anchor.BN(0.001 * anchor.web3.LAMPORTS_PER_SOL))
+      .accounts({
+        payer: payer.publicKey,
+        user: userPubkey,
+        switchboardRequest: switchboardRequestKeypair.publicKey,
+        switchboardRequestEscrow: escrowPubkey,
+      })
+      .rpc();
+    console.log(`[TX] fund_request_escrow: ${tx}`);
+
+    const finalBalance = await program.provider.connection
+      .getTokenAccountBalance(escrowPubkey)
+      .then((balance) => Number(balance.value.amount));
+    assert.equal(
+      finalBalance - initialBalance,
+      0.001 * anchor.web3.LAMPORTS_PER_SOL
+    );
+  });
+
   it("guess", async () => {
     const tx = await program.methods
-      .guess(1)
+      .guess(1, new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL), 1, 10, null)
       .accounts({
         payer: payer.publicKey,
+        programState: programStatePubkey,
         user: userPubkey,
         authority: payer.publicKey,
+        signer: payer.publicKey,
+        house: housePubkey,
+        stats: statsPubkey,
         switchboard: switchboard.program.attestationProgramId,
         switchboardState: switchboard.program.attestationProgramState.publicKey,
         switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
@@ -113,16 +584,114 @@ describe("switchboard-randomness-callback", () => {
       })
       .rpc();
     console.log(`[TX] guess: ${tx}`);
+
+    const userState = await program.account.userState.fetch(userPubkey);
+    assert.equal(userState.bounty.toNumber(), 0);
+    assert.equal(userState.slotsUntilExpiration.toNumber(), 300);
+    assert.equal(
+      userState.fee.toNumber(),
+      0.01 * anchor.web3.LAMPORTS_PER_SOL * 0.025
+    );
+
+    // guess tops up the request escrow to the configured minimum
+    const escrowBalance = await program.provider.connection.getTokenAccountBalance(
+      switchboard.program.mint.getAssociatedAddress(
+        switchboardRequestKeypair.publicKey
+      )
+    );
+    assert.isAtLeast(
+      Number(escrowBalance.value.amount),
+      0.002 * anchor.web3.LAMPORTS_PER_SOL
+    );
+  });
+
+  it("guess rejects a new round while one is pending", async () => {
+    try {
+      await program.methods
+        .guess(2, new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL), 1, 10, null)
+        .accounts({
+          payer: payer.publicKey,
+          programState: programStatePubkey,
+          user: userPubkey,
+          authority: payer.publicKey,
+          signer: payer.publicKey,
+          house: housePubkey,
+          stats: statsPubkey,
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: switchboardRequestKeypair.publicKey,
+          switchboardRequestEscrow:
+            switchboard.program.mint.getAssociatedAddress(
+              switchboardRequestKeypair.publicKey
+            ),
+        })
+        .rpc();
+      assert.fail("guess should have rejected the pending round");
+    } catch (error) {
+      assert.include(`${error}`, "RequestNotReady");
+    }
+  });
+
+  it("cancel_guess rejects unexpired requests", async () => {
+    try {
+      await program.methods
+        .cancelGuess()
+        .accounts({
+          user: userPubkey,
+          authority: payer.publicKey,
+          house: housePubkey,
+          programState: programStatePubkey,
+          switchboardRequest: switchboardRequestKeypair.publicKey,
+        })
+        .rpc();
+      assert.fail("cancel_guess should have required an expired request");
+    } catch (error) {
+      assert.include(`${error}`, "RequestNotExpired");
+    }
+  });
+
+  it("close rejects a pending guess", async () => {
+    try {
+      await program.methods
+        .close()
+        .accounts({
+          user: userPubkey,
+          authority: payer.publicKey,
+          escrowDest: await switchboard.program.mint.getOrCreateAssociatedUser(
+            payer.publicKey
+          ),
+          switchboard: switchboard.program.attestationProgramId,
+          switchboardState:
+            switchboard.program.attestationProgramState.publicKey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: switchboardRequestKeypair.publicKey,
+          switchboardRequestEscrow:
+            switchboard.program.mint.getAssociatedAddress(
+              switchboardRequestKeypair.publicKey
+            ),
+          switchboardMint: switchboard.program.mint.address,
+        })
+        .rpc();
+      assert.fail("close should have required the guess to be settled");
+    } catch (error) {
+      assert.include(`${error}`, "GuessPending");
+    }
+
+    const houseState = await program.account.houseVault.fetch(housePubkey);
+    assert.isAbove(houseState.outstandingLiability.toNumber(), 0);
   });
 
   ///////////////////////////////////////////////////////
   // Mock off-chain settle logic
   ///////////////////////////////////////////////////////
-  it("settle", async () => {
-    // First, generate a new keypair to sign our instruction
-    // Normally this happens within the enclave
-    const enclaveSigner = anchor.web3.Keypair.generate();
-
+  // Generates the function_request_verify ixn that must run before any of our emitted
+  // instructions. Normally this happens within the enclave.
+  async function buildRequestVerifyIxn(
+    enclaveSigner: anchor.web3.Keypair
+  ): Promise<anchor.web3.TransactionInstruction> {
     // Load the Switchboard account states
     const [_sbRequestAccount, sbRequestState] =
       await FunctionRequestAccount.load(
@@ -135,9 +704,7 @@ describe("switchboard-randomness-callback", () => {
     const rewardAddress =
       await switchboard.program.mint.getOrCreateAssociatedUser(payer.publicKey);
 
-    // Next, generate the function_request_verify ixn that we must call before running
-    // any of our emitted instructions.
-    const fnRequestVerifyIxn = attestationTypes.functionRequestVerify(
+    return attestationTypes.functionRequestVerify(
       switchboard.program,
       {
         params: {
@@ -163,14 +730,104 @@ describe("switchboard-randomness-callback", () => {
         tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
       }
     );
+  }
+
+  // A function on the same queue that the program state doesn't point to
+  let rogueFunction: FunctionAccount;
+
+  it("settle rejects a function other than the one in the program state", async () => {
+    // A rogue function on the same queue passes validate_request, so only the request and
+    // program state bindings can reject it
+    [rogueFunction] =
+      await switchboard.attestationQueue.account.createFunction({
+        name: "rogue function",
+        metadata: "settles guesses with an attacker controlled container",
+        container: "org/rogue-container",
+        version: "latest",
+        mrEnclave: MRENCLAVE,
+        recentSlot: await provider.connection.getSlot(),
+      });
+
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .settle(1)
+        .accounts({
+          user: userPubkey,
+          switchboardFunction: rogueFunction.publicKey,
+          switchboardRequest: switchboardRequestKeypair.publicKey,
+          enclaveSigner: enclaveSigner.publicKey,
+          house: housePubkey,
+          authority: payer.publicKey,
+          stats: statsPubkey,
+          programState: programStatePubkey,
+          treasury: treasuryPubkey,
+        })
+        .preInstructions([await buildRequestVerifyIxn(enclaveSigner)])
+        .signers([enclaveSigner, switchboard.verifier.signer])
+        .rpc();
+      assert.fail("settle should have rejected the rogue function");
+    } catch (error) {
+      assert.include(`${error}`, "InvalidSwitchboardFunction");
+    }
+
+    const userState = await program.account.userState.fetch(userPubkey);
+    assert.equal(userState.settledTimestamp.toNumber(), 0);
+  });
+
+  it("settle rejects a request made by another function", async () => {
+    // The rogue functions own request, passed with the legitimate function
+    const [rogueRequest] = await rogueFunction.createRequest({
+      containerParams: Buffer.from(""),
+    });
+
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    try {
+      await program.methods
+        .settle(1)
+        .accounts({
+          user: userPubkey,
+          switchboardFunction: switchboardFunction.publicKey,
+          switchboardRequest: rogueRequest.publicKey,
+          enclaveSigner: enclaveSigner.publicKey,
+          house: housePubkey,
+          authority: payer.publicKey,
+          stats: statsPubkey,
+          programState: programStatePubkey,
+          treasury: treasuryPubkey,
+        })
+        .signers([enclaveSigner])
+        .rpc();
+      assert.fail("settle should have rejected the rogue request");
+    } catch (error) {
+      // The user is bound to its own request before the request is checked
+      // against the function
+      assert.include(`${error}`, "ConstraintHasOne");
+    }
+
+    const userState = await program.account.userState.fetch(userPubkey);
+    assert.equal(userState.settledTimestamp.toNumber(), 0);
+  });
+
+  it("settle", async () => {
+    // First, generate a new keypair to sign our instruction
+    // Normally this happens within the enclave
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    const fnRequestVerifyIxn = await buildRequestVerifyIxn(enclaveSigner);
 
+    // The highest result in the guess' [1, 10] range must settle
     const tx = await program.methods
-      .settle(1)
+      .settle(10)
       .accounts({
         user: userPubkey,
         switchboardFunction: switchboardFunction.publicKey,
         switchboardRequest: switchboardRequestKeypair.publicKey,
         enclaveSigner: enclaveSigner.publicKey,
+        house: housePubkey,
+        authority: payer.publicKey,
+        stats: statsPubkey,
+        programState: programStatePubkey,
+        treasury: treasuryPubkey,
       })
       .preInstructions([fnRequestVerifyIxn])
       .signers([enclaveSigner, switchboard.verifier.signer])
@@ -178,10 +835,201 @@ describe("switchboard-randomness-callback", () => {
     console.log(`[TX] settle: ${tx}`);
 
     const userState = await program.account.userState.fetch(userPubkey);
+    assert.equal(userState.result, 10);
+    assert.equal(userState.historyLen, 1);
+    assert.equal(userState.history[0].result, userState.result);
+
+    const stats = await program.account.globalStats.fetch(statsPubkey);
+    assert.equal(stats.totalRounds.toNumber(), 1);
+    assert.equal(stats.resultHistogram[userState.result - 1].toNumber(), 1);
     if (userState.guess === userState.result) {
       console.log(`[RESULT] user won!`);
     } else {
       console.log(`[RESULT] user lost :(`);
     }
   });
+
+  it("settle accepts the lowest result in the range", async () => {
+    await program.methods
+      .guess(1, new anchor.BN(0.01 * anchor.web3.LAMPORTS_PER_SOL), 1, 10, null)
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        user: userPubkey,
+        authority: payer.publicKey,
+        signer: payer.publicKey,
+        house: housePubkey,
+        stats: statsPubkey,
+        switchboard: switchboard.program.attestationProgramId,
+        switchboardState: switchboard.program.attestationProgramState.publicKey,
+        switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: switchboardRequestKeypair.publicKey,
+        switchboardRequestEscrow: switchboard.program.mint.getAssociatedAddress(
+          switchboardRequestKeypair.publicKey
+        ),
+      })
+      .rpc();
+
+    const enclaveSigner = anchor.web3.Keypair.generate();
+    const tx = await program.methods
+      .settle(1)
+      .accounts({
+        user: userPubkey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: switchboardRequestKeypair.publicKey,
+        enclaveSigner: enclaveSigner.publicKey,
+        house: housePubkey,
+        authority: payer.publicKey,
+        stats: statsPubkey,
+        programState: programStatePubkey,
+        treasury: treasuryPubkey,
+      })
+      .preInstructions([await buildRequestVerifyIxn(enclaveSigner)])
+      .signers([enclaveSigner, switchboard.verifier.signer])
+      .rpc();
+    console.log(`[TX] settle: ${tx}`);
+
+    const userState = await program.account.userState.fetch(userPubkey);
+    assert.equal(userState.result, 1);
+    assert.equal(userState.historyLen, 2);
+
+    const stats = await program.account.globalStats.fetch(statsPubkey);
+    assert.equal(stats.totalRounds.toNumber(), 2);
+  });
+
+  it("cancel_guess refunds the full wager", async () => {
+    // Use the shortest expiration so the guess can be cancelled quickly
+    await program.methods
+      .updateConfig(
+        new anchor.BN(60),
+        null,
+        null,
+        null,
+        new anchor.BN(150),
+        null,
+        null
+      )
+      .accounts({ programState: programStatePubkey, authority: payer.publicKey })
+      .rpc();
+
+    const houseBalance = await provider.connection.getBalance(housePubkey);
+    const treasuryBalance = await provider.connection.getBalance(
+      treasuryPubkey
+    );
+    const { totalCollected } = await program.account.treasury.fetch(
+      treasuryPubkey
+    );
+
+    const wager = 0.01 * anchor.web3.LAMPORTS_PER_SOL;
+    await program.methods
+      .guess(1, new anchor.BN(wager), 1, 10, null)
+      .accounts({
+        payer: payer.publicKey,
+        programState: programStatePubkey,
+        user: userPubkey,
+        authority: payer.publicKey,
+        signer: payer.publicKey,
+        house: housePubkey,
+        stats: statsPubkey,
+        switchboard: switchboard.program.attestationProgramId,
+        switchboardState: switchboard.program.attestationProgramState.publicKey,
+        switchboardAttestationQueue: switchboard.attestationQueue.publicKey,
+        switchboardFunction: switchboardFunction.publicKey,
+        switchboardRequest: switchboardRequestKeypair.publicKey,
+        switchboardRequestEscrow: switchboard.program.mint.getAssociatedAddress(
+          switchboardRequestKeypair.publicKey
+        ),
+      })
+      .rpc();
+
+    // Wait for the request to expire and the request timeout to pass
+    const [_sbRequestAccount, sbRequestState] =
+      await FunctionRequestAccount.load(
+        switchboard.program,
+        switchboardRequestKeypair.publicKey
+      );
+    const expirationSlot = sbRequestState.activeRequest.expirationSlot;
+    const { requestTimestamp } = await program.account.userState.fetch(
+      userPubkey
+    );
+    while (
+      (await provider.connection.getSlot()) <= expirationSlot.toNumber() ||
+      Date.now() / 1000 - requestTimestamp.toNumber() <= 61
+    ) {
+      await sleep(2000);
+    }
+
+    const userBalance = await provider.connection.getBalance(payer.publicKey);
+    const tx = await program.methods
+      .cancelGuess()
+      .accounts({
+        user: userPubkey,
+        authority: payer.publicKey,
+        house: housePubkey,
+        programState: programStatePubkey,
+        switchboardRequest: switchboardRequestKeypair.publicKey,
+      })
+      .rpc();
+    console.log(`[TX] cancel_guess: ${tx}`);
+
+    // The full wager is refunded and the treasury never collects a fee
+    assert.equal(
+      await provider.connection.getBalance(payer.publicKey),
+      userBalance + wager - 5000
+    );
+    assert.equal(
+      await provider.connection.getBalance(housePubkey),
+      houseBalance
+    );
+    assert.equal(
+      await provider.connection.getBalance(treasuryPubkey),
+      treasuryBalance
+    );
+    const treasuryState = await program.account.treasury.fetch(treasuryPubkey);
+    assert.equal(
+      treasuryState.totalCollected.toNumber(),
+      totalCollected.toNumber()
+    );
+  });
+
+  it("withdraw_liquidity", async () => {
+    const shares = await provider.connection.getTokenAccountBalance(
+      providerSharesPubkey
+    );
+    const tx = await program.methods
+      .withdrawLiquidity(new anchor.BN(shares.value.amount))
+      .accounts({
+        provider: payer.publicKey,
+        house: housePubkey,
+        shareMint: shareMintPubkey,
+        providerShares: providerSharesPubkey,
+      })
+      .rpc();
+    console.log(`[TX] withdraw_liquidity: ${tx}`);
+
+    const houseState = await program.account.houseVault.fetch(housePubkey);
+    assert.equal(houseState.outstandingLiability.toNumber(), 0);
+  });
+
+  it("withdraw_fees", async () => {
+    const treasuryState = await program.account.treasury.fetch(treasuryPubkey);
+    // One protocol fee was collected for each of the two settled guesses, while
+    // the cancelled guess paid none
+    assert.equal(
+      treasuryState.totalCollected.toNumber(),
+      2 * 0.01 * anchor.web3.LAMPORTS_PER_SOL * 0.025
+    );
+
+    const tx = await program.methods
+      .withdrawFees(treasuryState.totalCollected)
+      .accounts({
+        programState: programStatePubkey,
+        authority: payer.publicKey,
+        treasury: treasuryPubkey,
+        destination: payer.publicKey,
+      })
+      .rpc();
+    console.log(`[TX] withdraw_fees: ${tx}`);
+  });
 });
@@ -30,3 +30,45 @@ export async function loadSwitchboard(
 
   return [switchboard, switchboardFunction];
 }
+
+/** Builds an idempotent create associated token account instruction. */
+export function createAssociatedTokenAccountIxn(
+  payer: anchor.web3.PublicKey,
+  owner: anchor.web3.PublicKey,
+  mint: anchor.web3.PublicKey
+): anchor.web3.TransactionInstruction {
+  return new anchor.web3.TransactionInstruction({
+    programId: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+    keys: [
+      { pubkey: payer, isSigner: true, isWritable: true },
+      {
+        pubkey: anchor.utils.token.associatedAddress({ mint, owner }),
+        isSigner: false,
+        isWritable: true,
+      },
+      { pubkey: owner, isSigner: false, isWritable: false },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      {
+        pubkey: anchor.web3.SystemProgram.programId,
+        isSigner: false,
+        isWritable: false,
+      },
+      {
+        pubkey: anchor.utils.token.TOKEN_PROGRAM_ID,
+        isSigner: false,
+        isWritable: false,
+      },
+    ],
+    data: Buffer.from([1]),
+  });
+}
+
+/** Derives the ProgramData account holding a program's upgrade authority. */
+export function getProgramDataAddress(
+  programId: anchor.web3.PublicKey
+): anchor.web3.PublicKey {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [programId.toBytes()],
+    new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  )[0];
+}